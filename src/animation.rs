@@ -0,0 +1,315 @@
+use image::codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder};
+use image::metadata::Orientation;
+use image::{
+    AnimationDecoder, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageResult, RgbaImage,
+};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Seek};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Delays shorter than this are taken to mean "as fast as possible" by old encoders, and are
+/// played at [`DEFAULT_DELAY`] instead, like web browsers do.
+const MIN_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// A single picture of an animation.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The whole canvas with the frame drawn onto it, so every frame has the same size.
+    pub picture: RgbaImage,
+    /// How long the frame stays on screen.
+    pub delay: Duration,
+}
+
+/// An animated GIF, APNG or WebP.
+///
+/// Only the first frame is kept in memory. The others are decoded as they are played, reading
+/// the file anew on every loop, so that long animations don't need a canvas worth of memory per
+/// frame.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    path: PathBuf,
+    format: ImageFormat,
+    first: Frame,
+    /// How many times the frames are played, or `None` to loop forever.
+    pub plays: Option<u32>,
+}
+
+impl Animation {
+    /// Opens the animation at `path` and decodes its first frame.
+    ///
+    /// Returns `None` if the file is not in an animated format, or holds only a single frame.
+    pub fn open(path: &Path) -> ImageResult<Option<Animation>> {
+        let bytes = std::fs::read(path)?;
+        let Ok(format) = image::guess_format(&bytes) else {
+            return Ok(None);
+        };
+        let Some(mut frames) = decode(Cursor::new(&bytes[..]), format)? else {
+            return Ok(None);
+        };
+        let Some(first) = frames.next().transpose()? else {
+            return Ok(None);
+        };
+        if frames.next().transpose()?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Animation {
+            path: path.to_owned(),
+            format,
+            first,
+            plays: plays(format, &bytes),
+        }))
+    }
+
+    /// The first frame, which is shown while the others are being prepared.
+    pub fn first(&self) -> &Frame {
+        &self.first
+    }
+
+    /// Decodes the frames from the start of the file, one at a time.
+    pub fn frames(&self) -> ImageResult<impl Iterator<Item = ImageResult<Frame>>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        decode(reader, self.format)?.ok_or_else(|| {
+            ImageError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The file is no longer animated",
+            ))
+        })
+    }
+}
+
+/// Sets up a decoder for the frames of an animation in `format`, or returns `None` if the
+/// format can't be animated or the file isn't.
+///
+/// Every frame is turned upright according to the EXIF orientation of the file, like still
+/// images are.
+fn decode<'a, R: BufRead + Seek + 'a>(
+    reader: R,
+    format: ImageFormat,
+) -> ImageResult<Option<impl Iterator<Item = ImageResult<Frame>> + 'a>> {
+    let (frames, orientation) = match format {
+        ImageFormat::Gif => {
+            let mut decoder = GifDecoder::new(reader)?;
+            let orientation = decoder.orientation()?;
+            (decoder.into_frames(), orientation)
+        }
+        ImageFormat::Png => {
+            let mut decoder = PngDecoder::new(reader)?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            let orientation = decoder.orientation()?;
+            (decoder.apng()?.into_frames(), orientation)
+        }
+        ImageFormat::WebP => {
+            let mut decoder = WebPDecoder::new(reader)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            let orientation = decoder.orientation()?;
+            (decoder.into_frames(), orientation)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(frames.map(move |frame| {
+        let frame = frame?;
+        let delay = Duration::from(frame.delay());
+        Ok(Frame {
+            delay: if delay < MIN_DELAY {
+                DEFAULT_DELAY
+            } else {
+                delay
+            },
+            picture: orient(frame.into_buffer(), orientation),
+        })
+    })))
+}
+
+/// Applies `orientation` to `picture`.
+fn orient(picture: RgbaImage, orientation: Orientation) -> RgbaImage {
+    if orientation == Orientation::NoTransforms {
+        return picture;
+    }
+    let mut image = DynamicImage::ImageRgba8(picture);
+    image.apply_orientation(orientation);
+    image.into_rgba8()
+}
+
+/// Reads how many times the animation is played from the file, as the decoders don't tell.
+///
+/// A count of zero means forever in all three formats, and is the only case that gives `None`.
+/// GIF counts the repetitions after the first play, while the others count plays. Files without
+/// a count, or with one that can't be read, are played once.
+fn plays(format: ImageFormat, bytes: &[u8]) -> Option<u32> {
+    match loop_count(format, bytes) {
+        Some(0) => None,
+        Some(count) => Some(count),
+        None => Some(1),
+    }
+}
+
+/// The number of plays stored in the file, where zero means forever.
+fn loop_count(format: ImageFormat, bytes: &[u8]) -> Option<u32> {
+    match format {
+        ImageFormat::Gif => {
+            // The NETSCAPE2.0 application extension, followed by its looping sub-block
+            let marker = b"NETSCAPE2.0\x03\x01";
+            let start = bytes.windows(marker.len()).position(|w| w == marker)? + marker.len();
+            let count = bytes.get(start..start + 2)?;
+            match u16::from_le_bytes([count[0], count[1]]) {
+                0 => Some(0),
+                repeats => Some(u32::from(repeats) + 1),
+            }
+        }
+        // The acTL chunk holds the number of frames and then the number of plays
+        ImageFormat::Png => Some(u32::from_be_bytes(
+            png_chunk(bytes, b"acTL")?.get(4..8)?.try_into().ok()?,
+        )),
+        // The ANIM chunk holds the background colour and then the loop count
+        ImageFormat::WebP => {
+            let anim = riff_chunk(bytes, b"ANIM")?;
+            Some(u16::from_le_bytes(anim.get(4..6)?.try_into().ok()?).into())
+        }
+        _ => None,
+    }
+}
+
+/// Finds the data of the first chunk of `kind` in a PNG file.
+fn png_chunk<'a>(bytes: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    // Skip the signature, then each chunk is a length, a type, the data and a checksum
+    let mut offset = 8;
+    loop {
+        let length = u32::from_be_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let data = offset + 8;
+        if bytes.get(offset + 4..data)? == kind {
+            return bytes.get(data..data + length);
+        }
+        offset = data + length + 4;
+    }
+}
+
+/// Finds the data of the first chunk of `kind` in a WebP file.
+fn riff_chunk<'a>(bytes: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    // Skip the RIFF header, then each chunk is a type, a length and the data padded to an even
+    // length
+    let mut offset = 12;
+    loop {
+        let length =
+            u32::from_le_bytes(bytes.get(offset + 4..offset + 8)?.try_into().ok()?) as usize;
+        let data = offset + 8;
+        if bytes.get(offset..offset + 4)? == kind {
+            return bytes.get(data..data + length);
+        }
+        offset = data + length + length % 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG made of the given chunks, with dummy checksums.
+    fn png(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, data) in chunks {
+            bytes.extend((data.len() as u32).to_be_bytes());
+            bytes.extend(*kind);
+            bytes.extend(*data);
+            bytes.extend([0; 4]);
+        }
+        bytes
+    }
+
+    /// A WebP made of the given chunks.
+    fn webp(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+        for (kind, data) in chunks {
+            body.extend(*kind);
+            body.extend((data.len() as u32).to_le_bytes());
+            body.extend(*data);
+            if data.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((body.len() as u32).to_le_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    /// A GIF header followed by a NETSCAPE2.0 block repeating `repeats` times.
+    fn gif(repeats: u16) -> Vec<u8> {
+        let mut bytes = b"GIF89a\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        bytes.extend(b"\x21\xff\x0bNETSCAPE2.0\x03\x01");
+        bytes.extend(repeats.to_le_bytes());
+        bytes.push(0);
+        bytes
+    }
+
+    #[test]
+    fn finds_png_chunks() {
+        let bytes = png(&[(b"IHDR", &[1; 13]), (b"acTL", &[0, 0, 0, 5, 0, 0, 0, 3])]);
+        assert_eq!(png_chunk(&bytes, b"IHDR"), Some(&[1; 13][..]));
+        assert_eq!(
+            png_chunk(&bytes, b"acTL"),
+            Some(&[0, 0, 0, 5, 0, 0, 0, 3][..])
+        );
+        assert_eq!(png_chunk(&bytes, b"IDAT"), None);
+        // A chunk running past the end of the file is not returned
+        assert_eq!(png_chunk(&bytes[..bytes.len() - 8], b"acTL"), None);
+    }
+
+    #[test]
+    fn finds_riff_chunks_after_odd_lengths() {
+        let bytes = webp(&[(b"VP8X", &[0; 9]), (b"ANIM", &[0, 0, 0, 0, 2, 0])]);
+        assert_eq!(riff_chunk(&bytes, b"VP8X"), Some(&[0; 9][..]));
+        assert_eq!(riff_chunk(&bytes, b"ANIM"), Some(&[0, 0, 0, 0, 2, 0][..]));
+        assert_eq!(riff_chunk(&bytes, b"ANMF"), None);
+    }
+
+    #[test]
+    fn reads_play_counts() {
+        let apng = |plays: u32| {
+            let mut actl = 4u32.to_be_bytes().to_vec();
+            actl.extend(plays.to_be_bytes());
+            png(&[(b"IHDR", &[0; 13]), (b"acTL", &actl)])
+        };
+        assert_eq!(plays(ImageFormat::Png, &apng(3)), Some(3));
+        assert_eq!(plays(ImageFormat::Png, &apng(0)), None);
+        let animated_webp = |count: u16| {
+            let mut anim = vec![0; 4];
+            anim.extend(count.to_le_bytes());
+            webp(&[(b"VP8X", &[0; 10]), (b"ANIM", &anim)])
+        };
+        assert_eq!(plays(ImageFormat::WebP, &animated_webp(2)), Some(2));
+        assert_eq!(plays(ImageFormat::WebP, &animated_webp(0)), None);
+        // GIF counts the repetitions after the first play
+        assert_eq!(plays(ImageFormat::Gif, &gif(2)), Some(3));
+        assert_eq!(plays(ImageFormat::Gif, &gif(0)), None);
+    }
+
+    #[test]
+    fn orients_frames() {
+        let picture = RgbaImage::from_fn(2, 1, |x, _| image::Rgba([x as u8, 0, 0, 255]));
+        assert_eq!(orient(picture.clone(), Orientation::NoTransforms), picture);
+        let turned = orient(picture.clone(), Orientation::Rotate90);
+        assert_eq!(turned.dimensions(), (1, 2));
+        assert_eq!(turned.get_pixel(0, 1), picture.get_pixel(1, 0));
+    }
+
+    #[test]
+    fn unreadable_play_counts_play_once() {
+        assert_eq!(plays(ImageFormat::Gif, b"GIF89a"), Some(1));
+        let truncated = gif(0);
+        assert_eq!(
+            plays(ImageFormat::Gif, &truncated[..truncated.len() - 3]),
+            Some(1)
+        );
+        assert_eq!(
+            plays(ImageFormat::Png, &png(&[(b"IHDR", &[0; 13])])),
+            Some(1)
+        );
+        assert_eq!(plays(ImageFormat::WebP, &webp(&[])), Some(1));
+    }
+}
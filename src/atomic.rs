@@ -0,0 +1,123 @@
+use crate::Card;
+use drm::control::{
+    atomic::AtomicModeReq, crtc, framebuffer, plane, property, AtomicCommitFlags, Device as _,
+    Mode, RawResourceHandle, ResourceHandle,
+};
+use eyre::{bail, Result};
+use std::{collections::HashMap, io};
+
+/// An atomic commit request that addresses properties by name.
+pub struct AtomicRequest<'a> {
+    card: &'a Card,
+    request: AtomicModeReq,
+    /// The property handles of every object touched so far, keyed by property name.
+    properties: HashMap<RawResourceHandle, HashMap<String, property::Handle>>,
+    /// Blobs created for the request, freed along with it.
+    blobs: Vec<u64>,
+}
+
+impl<'a> AtomicRequest<'a> {
+    pub fn new(card: &'a Card) -> AtomicRequest<'a> {
+        AtomicRequest {
+            card,
+            request: AtomicModeReq::new(),
+            properties: HashMap::new(),
+            blobs: Vec::new(),
+        }
+    }
+
+    /// Whether `handle` has a property called `name`.
+    pub fn has<T: ResourceHandle>(&mut self, handle: T, name: &str) -> Result<bool> {
+        Ok(self.property(handle, name)?.is_some())
+    }
+
+    /// Sets the property called `name` of `handle` to `value` once the request is committed.
+    pub fn set<T: ResourceHandle>(&mut self, handle: T, name: &str, value: u64) -> Result<()> {
+        let Some(property) = self.property(handle, name)? else {
+            bail!(
+                "Object {} has no {name} property",
+                Into::<u32>::into(handle)
+            );
+        };
+        self.request
+            .add_raw_property(handle.into(), property, value);
+        Ok(())
+    }
+
+    /// Sets a property of `handle` that refers to another object, like `CRTC_ID` or `FB_ID`.
+    pub fn set_object<T: ResourceHandle, O: ResourceHandle>(
+        &mut self,
+        handle: T,
+        name: &str,
+        object: O,
+    ) -> Result<()> {
+        self.set(handle, name, Into::<u32>::into(object).into())
+    }
+
+    /// Sets the `MODE_ID` property of a CRTC.
+    pub fn set_mode<T: ResourceHandle>(&mut self, crtc: T, mode: &Mode) -> Result<()> {
+        let property::Value::Blob(blob) = self.card.create_property_blob(mode)? else {
+            bail!("Failed to create a property blob for mode {mode:?}");
+        };
+        self.blobs.push(blob);
+        self.set(crtc, "MODE_ID", blob)
+    }
+
+    /// Attaches `fb` to `plane`, like [`drm::control::Device::set_plane`] does.
+    pub fn set_plane(
+        &mut self,
+        plane: plane::Handle,
+        crtc: crtc::Handle,
+        fb: framebuffer::Handle,
+        (crtc_x, crtc_y, crtc_w, crtc_h): (i32, i32, u32, u32),
+        (src_x, src_y, src_w, src_h): (u32, u32, u32, u32),
+    ) -> Result<()> {
+        self.set_object(plane, "FB_ID", fb)?;
+        self.set_object(plane, "CRTC_ID", crtc)?;
+        self.set(plane, "CRTC_X", crtc_x as i64 as u64)?;
+        self.set(plane, "CRTC_Y", crtc_y as i64 as u64)?;
+        self.set(plane, "CRTC_W", crtc_w.into())?;
+        self.set(plane, "CRTC_H", crtc_h.into())?;
+        self.set(plane, "SRC_X", src_x.into())?;
+        self.set(plane, "SRC_Y", src_y.into())?;
+        self.set(plane, "SRC_W", src_w.into())?;
+        self.set(plane, "SRC_H", src_h.into())
+    }
+
+    /// Checks whether the driver would accept the request, without changing anything.
+    pub fn test(&self, flags: AtomicCommitFlags) -> io::Result<()> {
+        self.card
+            .atomic_commit(flags | AtomicCommitFlags::TEST_ONLY, self.request.clone())
+    }
+
+    pub fn commit(&self, flags: AtomicCommitFlags) -> io::Result<()> {
+        self.card.atomic_commit(flags, self.request.clone())
+    }
+
+    fn property<T: ResourceHandle>(
+        &mut self,
+        handle: T,
+        name: &str,
+    ) -> io::Result<Option<property::Handle>> {
+        let object = handle.into();
+        if !self.properties.contains_key(&object) {
+            let properties = self
+                .card
+                .properties(handle)?
+                .into_iter()
+                .map(|(name, (info, _))| (name, info.handle()))
+                .collect();
+            self.properties.insert(object, properties);
+        }
+        Ok(self.properties[&object].get(name).copied())
+    }
+}
+
+impl Drop for AtomicRequest<'_> {
+    fn drop(&mut self) {
+        // The kernel keeps its own reference to blobs that were committed
+        for &blob in &self.blobs {
+            let _ = self.card.destroy_property_blob(blob);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{Align, Geometry, ScaleMode};
+use image::{imageops, imageops::FilterType, Rgba, RgbaImage};
+use std::{fmt, str::FromStr};
+
+/// What fills the parts of the screen the image does not cover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    /// A solid colour.
+    Color(Rgba<u8>),
+    /// A blurred copy of the image, scaled to cover the whole screen.
+    Blur,
+}
+
+impl Background {
+    /// Paints the whole CRTC: the background, with the part of `picture` described by `geometry`
+    /// drawn on top.
+    ///
+    /// The result is exactly the size of the CRTC, so it is shown unscaled at the origin.
+    pub fn compose(&self, picture: &RgbaImage, geometry: &Geometry) -> (RgbaImage, Geometry) {
+        let (width, height) = geometry.crtc_size;
+        let mut canvas = match *self {
+            Background::Color(color) => RgbaImage::from_pixel(width, height, color),
+            Background::Blur => blurred(picture, geometry.crtc_size),
+        };
+        let visible = if geometry.is_scaled() {
+            geometry.resample(picture)
+        } else {
+            geometry
+                .src_rect()
+                .map(|(src_x, src_y, src_width, src_height)| {
+                    imageops::crop_imm(
+                        picture,
+                        src_x >> 16,
+                        src_y >> 16,
+                        src_width >> 16,
+                        src_height >> 16,
+                    )
+                    .to_image()
+                })
+        };
+        if let (Some((x, y, _, _)), Some(visible)) = (geometry.crtc_rect(), visible) {
+            imageops::overlay(&mut canvas, &visible, x.into(), y.into());
+        }
+        (
+            canvas,
+            Geometry::unscaled(geometry.crtc_size, geometry.crtc_size),
+        )
+    }
+}
+
+/// Scales `picture` to cover an area of `size` and blurs it heavily.
+fn blurred(picture: &RgbaImage, size: (u32, u32)) -> RgbaImage {
+    // Blurring a small copy and scaling it back up is much cheaper than blurring the whole area
+    let small_size = ((size.0 / 16).max(1), (size.1 / 16).max(1));
+    let fill = Geometry::new(
+        ScaleMode::Fill,
+        Align::Center,
+        picture.dimensions(),
+        small_size,
+    );
+    let Some(small) = fill.resample(picture) else {
+        return RgbaImage::from_pixel(size.0, size.1, Rgba([0, 0, 0, 255]));
+    };
+    let mut small = imageops::fast_blur(&small, 2.0);
+    // The background is opaque, even where the image is not
+    for pixel in small.pixels_mut() {
+        pixel.0[3] = 255;
+    }
+    imageops::resize(&small, size.0, size.1, FilterType::Triangle)
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Background, String> {
+        if s == "blur" {
+            return Ok(Background::Blur);
+        }
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        // from_str_radix also takes a sign, so check for digits first
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .filter(|channel| channel.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+        };
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Background::Color(Rgba([r, g, b, 255]))),
+            _ => Err(format!(
+                "Expected a colour like `#RRGGBB` or `blur`, got `{s}`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Background::Color(Rgba([r, g, b, _])) => write!(f, "#{r:02x}{g:02x}{b:02x}"),
+            Background::Blur => f.write_str("blur"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colours() {
+        let color = |r, g, b| Ok(Background::Color(Rgba([r, g, b, 255])));
+        assert_eq!("#ff8000".parse(), color(255, 128, 0));
+        assert_eq!("0a0B0c".parse(), color(10, 11, 12));
+        assert_eq!("blur".parse(), Ok(Background::Blur));
+        for invalid in [
+            "#+1+2+3", "#-10000", "#12345", "#1234567", "#gg0000", "red", "",
+        ] {
+            assert!(invalid.parse::<Background>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for background in [
+            Background::Color(Rgba([1, 171, 255, 255])),
+            Background::Blur,
+        ] {
+            assert_eq!(background.to_string().parse(), Ok(background));
+        }
+        assert_eq!(
+            Background::Color(Rgba([1, 171, 255, 255])).to_string(),
+            "#01abff"
+        );
+    }
+
+    #[test]
+    fn composes_onto_the_whole_crtc() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let picture = RgbaImage::from_pixel(2, 2, red);
+        let geometry = Geometry::new(ScaleMode::Center, Align::Center, (2, 2), (6, 4));
+        let (canvas, shown) = Background::Color(blue).compose(&picture, &geometry);
+        assert_eq!(canvas.dimensions(), (6, 4));
+        assert_eq!(shown, Geometry::unscaled((6, 4), (6, 4)));
+        let (x, y, _, _) = geometry.crtc_rect().unwrap();
+        assert_eq!((x, y), (2, 1));
+        for (px, py, &pixel) in canvas.enumerate_pixels() {
+            let inside = (2..4).contains(&px) && (1..3).contains(&py);
+            assert_eq!(pixel, if inside { red } else { blue }, "{px},{py}");
+        }
+    }
+
+    #[test]
+    fn scales_the_picture_onto_the_background() {
+        let picture = RgbaImage::from_pixel(2, 1, Rgba([0, 255, 0, 255]));
+        let geometry = Geometry::new(ScaleMode::Fit, Align::Center, (2, 1), (8, 8));
+        let (canvas, _) = Background::Color(Rgba([0, 0, 0, 255])).compose(&picture, &geometry);
+        assert_eq!(canvas.dimensions(), (8, 8));
+        assert_eq!(canvas.get_pixel(4, 4), &Rgba([0, 255, 0, 255]));
+        assert_eq!(canvas.get_pixel(4, 0), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn blurred_background_is_opaque() {
+        let picture = RgbaImage::from_fn(4, 4, |x, _| Rgba([255, 255, 255, (x * 60) as u8]));
+        let background = blurred(&picture, (64, 32));
+        assert_eq!(background.dimensions(), (64, 32));
+        assert!(background.pixels().all(|pixel| pixel.0[3] == 255));
+        let geometry = Geometry::new(ScaleMode::Center, Align::Center, (4, 4), (64, 32));
+        let (canvas, _) = Background::Blur.compose(&picture, &geometry);
+        assert_eq!(canvas.dimensions(), (64, 32));
+    }
+}
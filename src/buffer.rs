@@ -0,0 +1,127 @@
+use crate::{Card, PixelFormat};
+use drm::buffer::{self, Buffer, DrmFourcc, DrmModifier, PlanarBuffer};
+use drm::control::{dumbbuffer::DumbBuffer, framebuffer, Device as _, FbCmd2Flags};
+use image::RgbaImage;
+use std::io;
+
+/// A dumb buffer together with the framebuffer wrapping it.
+#[derive(Debug)]
+pub struct DumbFramebuffer {
+    pub buffer: DumbBuffer,
+    pub handle: framebuffer::Handle,
+}
+
+impl DumbFramebuffer {
+    /// Creates a framebuffer holding `picture` in `format`.
+    pub fn from_image(
+        card: &Card,
+        picture: &RgbaImage,
+        format: PixelFormat,
+    ) -> io::Result<DumbFramebuffer> {
+        let mut framebuffer = DumbFramebuffer::zeroed(card, picture.dimensions(), format)?;
+        if let Err(e) = framebuffer.write(card, picture, format) {
+            framebuffer.destroy(card)?;
+            return Err(e);
+        }
+        Ok(framebuffer)
+    }
+
+    /// Overwrites the buffer with `picture` in `format`, which has to be the format the
+    /// framebuffer was created with. Parts of the picture that don't fit are cut off.
+    pub fn write(
+        &mut self,
+        card: &Card,
+        picture: &RgbaImage,
+        format: PixelFormat,
+    ) -> io::Result<()> {
+        let buffer_size = self.buffer.size();
+        let pitch = self.buffer.pitch();
+        let bytes = format.bpp() as usize / 8;
+        let mut mapping = card.map_dumb_buffer(&mut self.buffer)?;
+        for (x, y, &pixel) in picture.enumerate_pixels() {
+            if x >= buffer_size.0 {
+                continue;
+            }
+            if y >= buffer_size.1 {
+                break;
+            }
+            let index = x as usize * bytes + y as usize * pitch as usize;
+            format.pack(pixel, &mut mapping[index..]);
+        }
+        Ok(())
+    }
+
+    /// Creates a black Xrgb8888 framebuffer of the given size.
+    pub fn black(card: &Card, size: (u32, u32)) -> io::Result<DumbFramebuffer> {
+        DumbFramebuffer::zeroed(card, size, PixelFormat::Xrgb8888)
+    }
+
+    /// Creates a framebuffer of the given size filled with zeros, which is transparent black in
+    /// formats with alpha.
+    pub fn zeroed(
+        card: &Card,
+        size: (u32, u32),
+        format: PixelFormat,
+    ) -> io::Result<DumbFramebuffer> {
+        // Dumb buffers are zeroed on creation
+        let buffer = card.create_dumb_buffer(size, format.fourcc(), format.bpp())?;
+        DumbFramebuffer::wrap(card, buffer, format)
+    }
+
+    /// Wraps `buffer` in a framebuffer with the exact fourcc of `format`, falling back to the
+    /// legacy depth and bpp for drivers that reject ADDFB2. Frees the buffer if both fail.
+    fn wrap(card: &Card, buffer: DumbBuffer, format: PixelFormat) -> io::Result<DumbFramebuffer> {
+        let handle = card
+            .add_planar_framebuffer(&Planar(&buffer), FbCmd2Flags::empty())
+            .or_else(|e| match format.legacy_depth() {
+                Some(depth) => card.add_framebuffer(&buffer, depth, format.bpp()),
+                None => Err(e),
+            });
+        match handle {
+            Ok(handle) => Ok(DumbFramebuffer { buffer, handle }),
+            Err(e) => {
+                card.destroy_dumb_buffer(buffer)?;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.buffer.size()
+    }
+
+    /// Removes the framebuffer and frees the buffer behind it.
+    pub fn destroy(self, card: &Card) -> io::Result<()> {
+        card.destroy_framebuffer(self.handle)?;
+        card.destroy_dumb_buffer(self.buffer)
+    }
+}
+
+/// A dumb buffer seen as a planar buffer with a single plane, for the ADDFB2 ioctl.
+struct Planar<'a>(&'a DumbBuffer);
+
+impl PlanarBuffer for Planar<'_> {
+    fn size(&self) -> (u32, u32) {
+        Buffer::size(self.0)
+    }
+
+    fn format(&self) -> DrmFourcc {
+        Buffer::format(self.0)
+    }
+
+    fn modifier(&self) -> Option<DrmModifier> {
+        None
+    }
+
+    fn pitches(&self) -> [u32; 4] {
+        [self.0.pitch(), 0, 0, 0]
+    }
+
+    fn handles(&self) -> [Option<buffer::Handle>; 4] {
+        [Some(self.0.handle()), None, None, None]
+    }
+
+    fn offsets(&self) -> [u32; 4] {
+        [0; 4]
+    }
+}
@@ -0,0 +1,333 @@
+use crate::atomic::AtomicRequest;
+use crate::output::active_crtc;
+use crate::{find_connector, Card, DumbFramebuffer, Error, PixelFormat};
+use drm::buffer::{self, DrmFourcc, DrmModifier};
+use drm::control::{
+    self, connector, crtc, framebuffer, plane, AtomicCommitFlags, Device as _, ResourceHandles,
+};
+use drm::Device as _;
+use eyre::{bail, Result, WrapErr};
+use image::{imageops, RgbaImage};
+use rustix::event::{poll, PollFd, PollFlags};
+use rustix::mm;
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
+
+/// Set in the flags of GETFB2 when the framebuffer has a modifier.
+const DRM_MODE_FB_MODIFIERS: u32 = 2;
+
+/// How long to wait for the hardware to finish writing back a frame, in milliseconds.
+const WRITEBACK_TIMEOUT: i32 = 1000;
+
+/// Reads back the picture scanned out for the connector called `name`, or the first connected
+/// one if `None`.
+///
+/// Only the CRTC's own framebuffer is read, so overlay planes on top of it are missing.
+pub fn capture_connector(
+    card: &Card,
+    resources: &ResourceHandles,
+    name: Option<&str>,
+) -> Result<RgbaImage> {
+    let connector = find_connector(card, resources, name)?;
+    let Some((crtc, mode)) = active_crtc(card, &connector)? else {
+        bail!(Error::Resource(format!("Connector {connector} is not lit")));
+    };
+    let Some(fb) = crtc.framebuffer() else {
+        bail!(Error::Resource(format!(
+            "Connector {connector} shows no framebuffer"
+        )));
+    };
+    let picture = read_framebuffer(card, fb)?;
+    // The framebuffer may be larger than the mode, such as when the console spans several outputs
+    let (x, y) = crtc.position();
+    let (width, height) = mode.size();
+    Ok(imageops::crop_imm(&picture, x, y, width.into(), height.into()).to_image())
+}
+
+/// Captures the picture the CRTC of the connector called `name` (or the first connected one if
+/// `None`) sends to the display, with every plane composed on top of each other.
+///
+/// This needs a writeback connector that can be routed to the CRTC, which vkms and some
+/// embedded drivers have. Routing it there and back needs a modeset on most drivers, so the
+/// screen may flicker.
+pub fn capture_writeback(
+    card: &Card,
+    resources: &ResourceHandles,
+    name: Option<&str>,
+) -> Result<RgbaImage> {
+    // Look for the connector before the writeback connectors show up, so they are never picked
+    let connector = find_connector(card, resources, name)?;
+    let Some((crtc, mode)) = active_crtc(card, &connector)? else {
+        bail!(Error::Resource(format!("Connector {connector} is not lit")));
+    };
+    if !card.supports_atomic() {
+        bail!(Error::Resource(
+            "Writeback needs a driver with atomic modesetting".to_owned()
+        ));
+    }
+    card.enable_writeback()
+        .wrap_err("The kernel doesn't support writeback connectors")?;
+    let (writeback, format) = writeback_connector(card, crtc.handle())?;
+    let (width, height) = mode.size();
+    card.acquire_master_lock().map_err(Error::Master)?;
+    let picture = DumbFramebuffer::zeroed(card, (width.into(), height.into()), format)
+        .map_err(eyre::Report::from)
+        .and_then(|fb| {
+            let picture = write_back(card, writeback, crtc.handle(), fb.handle);
+            let _ = fb.destroy(card);
+            picture
+        });
+    let _ = card.release_master_lock();
+    picture
+}
+
+/// Finds a writeback connector that can be routed to `crtc`, along with the best format it can
+/// write.
+fn writeback_connector(
+    card: &Card,
+    crtc: crtc::Handle,
+) -> Result<(connector::Handle, PixelFormat)> {
+    let resources = card.resource_handles()?;
+    let mut found = false;
+    for &handle in resources.connectors() {
+        let connector = card.get_connector(handle, false)?;
+        if connector.interface() != connector::Interface::Writeback {
+            continue;
+        }
+        let mut crtcs = Vec::new();
+        for &encoder in connector.encoders() {
+            crtcs.extend(resources.filter_crtcs(card.get_encoder(encoder)?.possible_crtcs()));
+        }
+        if !crtcs.contains(&crtc) {
+            continue;
+        }
+        found = true;
+        let properties = card.property_values(handle)?;
+        let formats = match properties.get("WRITEBACK_PIXEL_FORMATS") {
+            Some(&blob) if blob != 0 => card.get_property_blob(blob)?,
+            _ => continue,
+        };
+        let formats: Vec<u32> = formats
+            .chunks_exact(4)
+            .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+            .collect();
+        if let Some(format) = PixelFormat::best(&formats) {
+            return Ok((handle, format));
+        }
+    }
+    if found {
+        bail!(Error::Format(
+            "No writeback connector can write any pixel format drmimage reads".to_owned()
+        ));
+    }
+    bail!(Error::Resource(
+        "Failed to find a writeback connector for the CRTC".to_owned()
+    ));
+}
+
+/// Attaches a writeback job writing into `fb` to the CRTC, waits for it to finish and reads the
+/// result.
+fn write_back(
+    card: &Card,
+    writeback: connector::Handle,
+    crtc: crtc::Handle,
+    fb: framebuffer::Handle,
+) -> Result<RgbaImage> {
+    let mut fence: i32 = -1;
+    let mut request = AtomicRequest::new(card);
+    request.set_object(writeback, "CRTC_ID", crtc)?;
+    request.set_object(writeback, "WRITEBACK_FB_ID", fb)?;
+    request.set(
+        writeback,
+        "WRITEBACK_OUT_FENCE_PTR",
+        &mut fence as *mut i32 as u64,
+    )?;
+    let committed = request
+        .commit(AtomicCommitFlags::ALLOW_MODESET)
+        .wrap_err("Failed to attach the writeback connector");
+    let written = committed.and_then(|()| {
+        if fence < 0 {
+            bail!("The driver didn't return a writeback fence");
+        }
+        // SAFETY: The kernel filled in a new file descriptor that nothing else owns.
+        let fence = unsafe { OwnedFd::from_raw_fd(fence) };
+        let mut fds = [PollFd::new(&fence, PollFlags::IN)];
+        if poll(&mut fds, WRITEBACK_TIMEOUT)? == 0 {
+            bail!("Timed out waiting for the writeback to finish");
+        }
+        Ok(())
+    });
+    // Unroute the writeback connector again, its job is gone once the frame has been written
+    let mut request = AtomicRequest::new(card);
+    request.set(writeback, "CRTC_ID", 0)?;
+    let _ = request.commit(AtomicCommitFlags::ALLOW_MODESET);
+    written?;
+    read_framebuffer(card, fb)
+}
+
+/// Reads back the framebuffer shown on `plane`.
+pub fn capture_plane(card: &Card, plane: plane::Handle) -> Result<RgbaImage> {
+    let Some(fb) = card.get_plane(plane)?.framebuffer() else {
+        bail!(Error::Resource(format!(
+            "Plane {} shows no framebuffer",
+            u32::from(plane)
+        )));
+    };
+    read_framebuffer(card, fb)
+}
+
+/// Reads the contents of framebuffer `fb` and converts them to RGBA.
+///
+/// The kernel only hands out the buffer behind a framebuffer to root or the DRM master, and only
+/// linear buffers in one of the [`PixelFormat`]s can be read.
+pub fn read_framebuffer(card: &Card, fb: framebuffer::Handle) -> Result<RgbaImage> {
+    let layout = FramebufferLayout::get(card, fb)?;
+    let Some(buffer) = layout.buffer else {
+        bail!(Error::Permission(format!(
+            "Failed to read framebuffer {}, as the kernel only hands out framebuffers to root or \
+             the DRM master",
+            u32::from(fb)
+        )));
+    };
+    let picture = layout.read(card, buffer);
+    // The handle is ours to close, even if the framebuffer was created by this process
+    let _ = card.close_buffer(buffer);
+    picture
+}
+
+/// Where the pixels of a framebuffer are and how they are laid out.
+struct FramebufferLayout {
+    size: (u32, u32),
+    format: PixelFormat,
+    buffer: Option<buffer::Handle>,
+    pitch: u32,
+    offset: u32,
+}
+
+impl FramebufferLayout {
+    /// Asks for the layout with GETFB2, which knows the exact format, falling back to GETFB on
+    /// kernels without it.
+    ///
+    /// The buffer handles the kernel creates for the caller are closed again if the framebuffer
+    /// can't be read.
+    fn get(card: &Card, fb: framebuffer::Handle) -> Result<FramebufferLayout> {
+        // The raw ioctl is used because the drm crate drops the buffer handles of framebuffers in
+        // formats it doesn't know, which would leak them
+        let info = match drm_ffi::mode::get_framebuffer2(card.as_fd(), fb.into()) {
+            Ok(info) => info,
+            Err(_) => {
+                let info = card.get_framebuffer(fb)?;
+                let Some(format) = PixelFormat::from_legacy(info.depth(), info.bpp()) else {
+                    close_buffers(card, [info.buffer()]);
+                    bail!(Error::Format(format!(
+                        "Framebuffer {} has depth {} and {} bpp, which drmimage can't read",
+                        u32::from(fb),
+                        info.depth(),
+                        info.bpp()
+                    )));
+                };
+                return Ok(FramebufferLayout {
+                    size: info.size(),
+                    format,
+                    buffer: info.buffer(),
+                    pitch: info.pitch(),
+                    offset: 0,
+                });
+            }
+        };
+        let buffers = info.handles.map(control::from_u32::<buffer::Handle>);
+        let Some(format) = DrmFourcc::try_from(info.pixel_format)
+            .ok()
+            .and_then(PixelFormat::from_fourcc)
+        else {
+            close_buffers(card, buffers);
+            bail!(Error::Format(format!(
+                "Framebuffer {} is in {}, which drmimage can't read",
+                u32::from(fb),
+                fourcc_name(info.pixel_format)
+            )));
+        };
+        if info.flags & DRM_MODE_FB_MODIFIERS != 0
+            && DrmModifier::from(info.modifier[0]) != DrmModifier::Linear
+        {
+            close_buffers(card, buffers);
+            bail!(Error::Format(format!(
+                "Framebuffer {} is tiled or compressed, which drmimage can't read",
+                u32::from(fb)
+            )));
+        }
+        Ok(FramebufferLayout {
+            size: (info.width, info.height),
+            format,
+            buffer: buffers[0],
+            pitch: info.pitches[0],
+            offset: info.offsets[0],
+        })
+    }
+
+    /// Maps `buffer` and converts its pixels.
+    fn read(&self, card: &Card, buffer: buffer::Handle) -> Result<RgbaImage> {
+        let (width, height) = self.size;
+        let length = self.offset as usize + self.pitch as usize * height as usize;
+        let map = drm_ffi::mode::dumbbuffer::map(card.as_fd(), buffer.into(), 0, 0)?;
+        // SAFETY: The kernel checks the offset and length against the size of the buffer, and
+        // the mapping is only read from and unmapped before returning.
+        let bytes = unsafe {
+            let pointer = mm::mmap(
+                std::ptr::null_mut(),
+                length,
+                mm::ProtFlags::READ,
+                mm::MapFlags::SHARED,
+                card.as_fd(),
+                map.offset,
+            )?;
+            std::slice::from_raw_parts(pointer as *const u8, length)
+        };
+        let bytes_per_pixel = self.format.bpp() as usize / 8;
+        let picture = RgbaImage::from_fn(width, height, |x, y| {
+            let index = self.offset as usize
+                + y as usize * self.pitch as usize
+                + x as usize * bytes_per_pixel;
+            self.format.unpack(&bytes[index..])
+        });
+        // SAFETY: `bytes` is not used past this point.
+        unsafe { mm::munmap(bytes.as_ptr() as *mut _, length)? };
+        Ok(picture)
+    }
+}
+
+/// Closes the buffer handles GETFB or GETFB2 created, each only once as the planes of a
+/// framebuffer may share a buffer.
+fn close_buffers(card: &Card, buffers: impl IntoIterator<Item = Option<buffer::Handle>>) {
+    let mut closed = Vec::new();
+    for buffer in buffers.into_iter().flatten() {
+        if !closed.contains(&buffer) {
+            let _ = card.close_buffer(buffer);
+            closed.push(buffer);
+        }
+    }
+}
+
+/// Spells out a fourcc code, such as XR24, for formats the drm crate doesn't know.
+fn fourcc_name(code: u32) -> String {
+    code.to_le_bytes()
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() {
+                byte as char
+            } else {
+                '?'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_fourcc_codes() {
+        assert_eq!(fourcc_name(u32::from_le_bytes(*b"XR24")), "XR24");
+        assert_eq!(fourcc_name(u32::from_le_bytes(*b"Y\0\n1")), "Y??1");
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{PlaneCapabilities, PlaneType};
+use drm::control::{
+    connector, crtc, plane, property, Device as _, ResourceHandle, ResourceHandles,
+};
+use drm::{ClientCapability, Device as _};
+use eyre::{bail, Result};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io,
+    os::fd::{AsFd, BorrowedFd},
+    path::{Path, PathBuf},
+};
+
+/// An opened DRM device node, such as `/dev/dri/card0`.
+pub struct Card {
+    file: File,
+    atomic: bool,
+}
+
+impl Card {
+    /// Opens the first `/dev/dri/cardN` node that has at least one connected connector.
+    ///
+    /// Render-only nodes and cards with nothing plugged in are skipped.
+    pub fn find_device() -> Option<Card> {
+        Card::find_matching(Card::has_connected_outputs)
+    }
+
+    /// Opens the first `/dev/dri/cardN` node driven by the kernel driver called `driver`, such as
+    /// `i915` or `vkms`.
+    pub fn find_driver(driver: &str) -> Option<Card> {
+        Card::find_matching(|card| card.driver_name().is_ok_and(|name| name == driver))
+    }
+
+    /// Opens every `/dev/dri/cardN` node, whether or not anything is connected to it, along with
+    /// its path.
+    pub fn open_all() -> Vec<(PathBuf, Card)> {
+        Card::nodes().collect()
+    }
+
+    fn find_matching(predicate: impl Fn(&Card) -> bool) -> Option<Card> {
+        Card::nodes()
+            .map(|(_, card)| card)
+            .find(|card| predicate(card))
+    }
+
+    /// Opens the card nodes one after the other, as they are consumed.
+    fn nodes() -> impl Iterator<Item = (PathBuf, Card)> {
+        (0..=255).filter_map(|i| {
+            let path = PathBuf::from(format!("/dev/dri/card{i}"));
+            Card::open(&path)
+                .inspect_err(|e| {
+                    if e.kind() != io::ErrorKind::NotFound {
+                        eprintln!("Failed to open {}: {e:?}", path.display());
+                    }
+                })
+                .ok()
+                .map(|card| (path, card))
+        })
+    }
+
+    /// Opens the DRM device node at `path`.
+    ///
+    /// Universal planes are enabled if the kernel supports them, so that primary planes can be
+    /// used when no overlay plane is free, and so is atomic modesetting.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Card> {
+        let mut card = Card {
+            file: OpenOptions::new().read(true).write(true).open(&path)?,
+            atomic: false,
+        };
+        let _ = card.set_client_capability(ClientCapability::UniversalPlanes, true);
+        card.atomic = card
+            .set_client_capability(ClientCapability::Atomic, true)
+            .is_ok();
+        Ok(card)
+    }
+
+    /// Makes the kernel list writeback connectors, which capture what a CRTC scans out into a
+    /// framebuffer.
+    ///
+    /// The kernel hides them unless asked to. drmimage only asks when capturing, so that they are
+    /// never picked for showing images.
+    pub fn enable_writeback(&self) -> io::Result<()> {
+        self.set_client_capability(ClientCapability::WritebackConnectors, true)
+    }
+
+    /// Whether the driver accepts atomic commits.
+    pub fn supports_atomic(&self) -> bool {
+        self.atomic
+    }
+
+    /// The name of the kernel driver behind the card.
+    pub fn driver_name(&self) -> io::Result<String> {
+        Ok(self.get_driver()?.name().to_string_lossy().into_owned())
+    }
+
+    /// Whether any of the card's connectors has a display connected.
+    pub fn has_connected_outputs(&self) -> bool {
+        let Ok(resources) = self.resource_handles() else {
+            return false;
+        };
+        resources.connectors().iter().any(|&handle| {
+            self.get_connector(handle, false)
+                .is_ok_and(|connector| connector.state() == connector::State::Connected)
+        })
+    }
+
+    /// Finds the best plane for showing an image on `crtc`, other than the planes in `exclude`.
+    ///
+    /// Candidates are unused overlay planes and the CRTC's primary plane, ranked by
+    /// [`PlaneCapabilities::score`]. Returns `None` if there is no candidate at all, which happens
+    /// on kernels without universal planes when all overlays are taken.
+    pub fn get_crtc_plane(
+        &self,
+        resources: &ResourceHandles,
+        crtc: crtc::Handle,
+        exclude: &[plane::Handle],
+    ) -> Result<Option<plane::Info>> {
+        let mut best: Option<(u32, plane::Info)> = None;
+        for handle in self.plane_handles()? {
+            if exclude.contains(&handle) {
+                continue;
+            }
+            let plane = self.get_plane(handle)?;
+            let capabilities = PlaneCapabilities::read(self, &plane)?;
+            if !capabilities.usable_on(resources, &plane, crtc) {
+                continue;
+            }
+            let score = capabilities.score();
+            if best.as_ref().map_or(true, |&(best, _)| score > best) {
+                best = Some((score, plane));
+            }
+        }
+        Ok(best.map(|(_, plane)| plane))
+    }
+
+    /// Finds the primary plane that can scan out a background on `crtc`, preferring the one
+    /// already attached to it.
+    pub fn get_primary_plane(
+        &self,
+        resources: &ResourceHandles,
+        crtc: crtc::Handle,
+    ) -> Result<Option<plane::Handle>> {
+        let mut found = None;
+        for handle in self.plane_handles()? {
+            let plane = self.get_plane(handle)?;
+            if !resources
+                .filter_crtcs(plane.possible_crtcs())
+                .contains(&crtc)
+                || PlaneCapabilities::read(self, &plane)?.kind != PlaneType::Primary
+            {
+                continue;
+            }
+            match plane.crtc() {
+                Some(c) if c == crtc => return Ok(Some(handle)),
+                Some(_) => {}
+                None => found = found.or(Some(handle)),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Reads the `type` property of a plane.
+    pub fn plane_type(&self, handle: plane::Handle) -> io::Result<PlaneType> {
+        Ok(PlaneCapabilities::read(self, &self.get_plane(handle)?)?.kind)
+    }
+
+    /// Reads the properties of `handle` along with their current values, keyed by property name.
+    pub fn properties<T: ResourceHandle>(
+        &self,
+        handle: T,
+    ) -> io::Result<HashMap<String, (property::Info, property::RawValue)>> {
+        let mut properties = HashMap::new();
+        for (&property, &value) in &self.get_properties(handle)? {
+            let info = self.get_property(property)?;
+            properties.insert(info.name().to_string_lossy().into_owned(), (info, value));
+        }
+        Ok(properties)
+    }
+
+    /// Sets the property called `name` of `handle` outside of an atomic commit.
+    pub fn set_property_by_name<T: ResourceHandle>(
+        &self,
+        handle: T,
+        name: &str,
+        value: property::RawValue,
+    ) -> Result<()> {
+        let Some((property, _)) = self.properties(handle)?.remove(name) else {
+            bail!(
+                "Object {} has no {name} property",
+                Into::<u32>::into(handle)
+            );
+        };
+        self.set_property(handle, property.handle(), value)?;
+        Ok(())
+    }
+
+    /// Reads the current values of the properties of `handle`, keyed by property name.
+    pub fn property_values<T: ResourceHandle>(
+        &self,
+        handle: T,
+    ) -> io::Result<HashMap<String, property::RawValue>> {
+        Ok(self
+            .properties(handle)?
+            .into_iter()
+            .map(|(name, (_, value))| (name, value))
+            .collect())
+    }
+}
+
+impl AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+impl drm::Device for Card {}
+impl drm::control::Device for Card {}
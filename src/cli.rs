@@ -0,0 +1,248 @@
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use drm::control::{self, plane};
+use drmimage::{Align, Axis, Background, DisplayOptions, Layout, Orientation, ScaleMode};
+use std::{ffi::OsString, path::PathBuf, time::Duration};
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Show an image until interrupted (the default when only an image is given)
+    Show(ShowArgs),
+    /// Print the driver, connectors, encoders, CRTCs and planes of every card, or of the one given
+    Info(InfoArgs),
+    /// Save the picture currently on screen to a PNG file
+    Capture(CaptureArgs),
+    /// Show a colour bar test pattern
+    Pattern(PatternArgs),
+}
+
+#[derive(Args)]
+pub struct DeviceArgs {
+    /// The DRM device to use, instead of the first one with a connected output
+    #[arg(long, value_name = "PATH")]
+    pub device: Option<PathBuf>,
+    /// Use the first card driven by this kernel driver, such as i915 or vkms
+    #[arg(long, value_name = "NAME", conflicts_with = "device")]
+    pub driver: Option<String>,
+}
+
+#[derive(Args)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    pub device: DeviceArgs,
+    /// Print human readable text, or JSON for other programs
+    #[arg(long, value_enum, default_value_t = InfoFormat::Text)]
+    pub format: InfoFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum InfoFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct OutputArgs {
+    /// The connector to show the image on, such as HDMI-A-1 or eDP-1
+    #[arg(long, value_name = "NAME")]
+    pub connector: Option<String>,
+    /// Show the image on every connected output, each scaled to its own mode
+    #[arg(long, conflicts_with = "connector")]
+    pub all_outputs: bool,
+    /// Span the image across several outputs, e.g. "HDMI-A-1 at 0,0; HDMI-A-2 at 1920,0"
+    #[arg(long, conflicts_with_all = ["connector", "all_outputs"])]
+    pub layout: Option<Layout>,
+    /// How to size the image to the screen: fit, fill, stretch, center or integer
+    #[arg(long, value_name = "MODE", default_value_t)]
+    pub scale: ScaleMode,
+    /// Which edges of the screen to place the image against, such as top-left, center or bottom
+    #[arg(long, default_value_t)]
+    pub align: Align,
+    /// Put the top-left corner of the image at this point of the screen, e.g. "-100,50"
+    #[arg(
+        long,
+        value_name = "X,Y",
+        value_parser = parse_position,
+        allow_hyphen_values = true,
+        conflicts_with = "align"
+    )]
+    pub position: Option<(i32, i32)>,
+    /// Fill the rest of the screen with a colour like "#RRGGBB", or "blur" for a blurred copy of
+    /// the image
+    #[arg(long, value_name = "COLOR")]
+    pub background: Option<Background>,
+    /// Use the plane with this ID instead of the best one available, for debugging
+    #[arg(
+        long,
+        value_name = "ID",
+        value_parser = parse_plane,
+        conflicts_with_all = ["all_outputs", "layout"]
+    )]
+    pub plane: Option<plane::Handle>,
+    /// Rotate the image clockwise by this many degrees: 0, 90, 180 or 270
+    #[arg(long, value_name = "DEGREES", default_value_t = 0, value_parser = parse_rotation)]
+    pub rotate: u32,
+    /// Mirror the image along the x or y axis, before rotating it
+    #[arg(long, value_name = "AXIS")]
+    pub reflect: Option<Axis>,
+}
+
+impl OutputArgs {
+    pub fn orientation(&self) -> Orientation {
+        let rotation = Orientation::rotate(self.rotate).unwrap_or_default();
+        match self.reflect {
+            Some(axis) => Orientation::reflect(axis).then(rotation),
+            None => rotation,
+        }
+    }
+
+    pub fn to_options(&self) -> DisplayOptions {
+        DisplayOptions {
+            connector: self.connector.clone(),
+            all_outputs: self.all_outputs,
+            layout: self.layout.clone(),
+            scale: self.scale,
+            align: self.align,
+            position: self.position,
+            background: self.background,
+            plane: self.plane,
+            orientation: self.orientation(),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ShowArgs {
+    #[command(flatten)]
+    pub device: DeviceArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Take the image down after this many seconds, instead of waiting for Ctrl+C
+    #[arg(long, value_name = "SECONDS", value_parser = parse_duration)]
+    pub duration: Option<Duration>,
+    /// The image to show, or several images each prefixed with the connector to show it on
+    #[arg(required = true, value_name = "[CONNECTOR=]IMAGE")]
+    pub images: Vec<PathBuf>,
+}
+
+impl ShowArgs {
+    /// Splits `CONNECTOR=IMAGE` arguments into the connector name and the path of the image.
+    ///
+    /// Anything with a `/` before the `=` is taken to be a path, so `./a=b.png` can be used to
+    /// show a file with `=` in its name.
+    pub fn images(&self) -> Vec<(Option<String>, PathBuf)> {
+        self.images
+            .iter()
+            .map(|image| {
+                let assignment = image.to_str().and_then(|image| image.split_once('='));
+                match assignment {
+                    Some((connector, path))
+                        if !connector.is_empty() && !connector.contains('/') =>
+                    {
+                        (Some(connector.to_owned()), PathBuf::from(path))
+                    }
+                    _ => (None, image.clone()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Args)]
+pub struct CaptureArgs {
+    #[command(flatten)]
+    pub device: DeviceArgs,
+    /// The connector whose picture to save, instead of the first connected one
+    #[arg(long, value_name = "NAME")]
+    pub connector: Option<String>,
+    /// Save the framebuffer shown on the plane with this ID instead
+    #[arg(long, value_name = "ID", value_parser = parse_plane, conflicts_with = "connector")]
+    pub plane: Option<plane::Handle>,
+    /// Save what the connector displays with all planes composed, using a writeback connector
+    #[arg(long, conflicts_with = "plane")]
+    pub writeback: bool,
+    /// Where to write the PNG file
+    pub output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct PatternArgs {
+    #[command(flatten)]
+    pub device: DeviceArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Take the pattern down after this many seconds, instead of waiting for Ctrl+C
+    #[arg(long, value_name = "SECONDS", value_parser = parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+impl Cli {
+    /// Parses the command line, treating `drmimage <image>` as `drmimage show <image>`.
+    pub fn parse_with_shorthand() -> Cli {
+        let mut args: Vec<OsString> = std::env::args_os().collect();
+        let is_subcommand = |arg: &OsString| {
+            let arg = arg.to_string_lossy();
+            matches!(
+                &*arg,
+                "show"
+                    | "info"
+                    | "capture"
+                    | "pattern"
+                    | "help"
+                    | "-h"
+                    | "--help"
+                    | "-V"
+                    | "--version"
+            )
+        };
+        if args.len() > 1 && !is_subcommand(&args[1]) {
+            args.insert(1, "show".into());
+        }
+        Cli::parse_from(args)
+    }
+
+    /// An error about arguments to `subcommand` that clap can't check by itself, which is
+    /// printed with the usage and exits with status 2 like clap's own errors.
+    pub fn usage_error(subcommand: &str, message: &str) -> clap::Error {
+        let mut command = Cli::command();
+        command.build();
+        match command.find_subcommand_mut(subcommand) {
+            Some(subcommand) => subcommand.error(ErrorKind::ArgumentConflict, message),
+            None => command.error(ErrorKind::ArgumentConflict, message),
+        }
+    }
+}
+
+fn parse_duration(seconds: &str) -> Result<Duration, String> {
+    let seconds: f64 = seconds.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("{e}"))
+}
+
+fn parse_position(position: &str) -> Result<(i32, i32), String> {
+    let Some((x, y)) = position.split_once(',') else {
+        return Err(format!(
+            "Expected a position like `100,50`, got `{position}`"
+        ));
+    };
+    let coordinate = |c: &str| c.trim().parse::<i32>().map_err(|e| format!("{e}"));
+    Ok((coordinate(x)?, coordinate(y)?))
+}
+
+fn parse_rotation(degrees: &str) -> Result<u32, String> {
+    match degrees.parse() {
+        Ok(degrees @ (0 | 90 | 180 | 270)) => Ok(degrees),
+        _ => Err(format!("Expected 0, 90, 180 or 270, got `{degrees}`")),
+    }
+}
+
+fn parse_plane(id: &str) -> Result<plane::Handle, String> {
+    let id: u32 = id.parse().map_err(|e| format!("{e}"))?;
+    control::from_u32(id).ok_or_else(|| "Plane IDs start at 1".to_owned())
+}
@@ -0,0 +1,112 @@
+use image::ImageError;
+use std::{error, fmt, io, path::PathBuf};
+
+/// The failures drmimage can give advice on.
+///
+/// Functions return these wrapped in an [`eyre::Report`], from which they can be recovered with
+/// [`Error::find`].
+#[derive(Debug)]
+pub enum Error {
+    /// No DRM device could be found or opened.
+    Device {
+        description: String,
+        source: Option<io::Error>,
+    },
+    /// Becoming DRM master failed, so nothing can be shown.
+    Master(io::Error),
+    /// The kernel only allows root or the DRM master to do this.
+    Permission(String),
+    /// There is no connector, CRTC or plane that can show the image.
+    Resource(String),
+    /// No plane can scan out any pixel format drmimage writes.
+    Format(String),
+    /// The image could not be read or decoded.
+    Decode { path: PathBuf, source: ImageError },
+}
+
+/// `EBUSY`, which the kernel returns when another process holds DRM master.
+const EBUSY: i32 = 16;
+
+impl Error {
+    /// Finds the [`Error`] behind `report`, if there is one.
+    pub fn find(report: &eyre::Report) -> Option<&Error> {
+        report.chain().find_map(|e| e.downcast_ref::<Error>())
+    }
+
+    /// What the user can try to fix the problem.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::Device {
+                source: Some(e), ..
+            } if e.kind() == io::ErrorKind::PermissionDenied => {
+                Some("Run drmimage as root, or add your user to the video group")
+            }
+            Error::Device { .. } => Some(
+                "Check that a DRM driver is loaded and that /dev/dri contains card nodes, or pick \
+                 one with --device or --driver",
+            ),
+            Error::Master(e)
+                if e.kind() == io::ErrorKind::PermissionDenied
+                    || e.raw_os_error() == Some(EBUSY) =>
+            {
+                Some(
+                    "Another process such as Xorg or a Wayland compositor holds DRM master. \
+                     Switch to a text console with Ctrl+Alt+F3 or stop the compositor, and run \
+                     drmimage as root or from the active console",
+                )
+            }
+            Error::Master(_) => None,
+            Error::Permission(_) => Some("Run drmimage as root"),
+            Error::Resource(_) => {
+                Some("Run `drmimage info` to see the connectors, CRTCs and planes of the card")
+            }
+            Error::Format(_) => Some("Run `drmimage info` to see the formats each plane supports"),
+            Error::Decode {
+                source: ImageError::IoError(_),
+                ..
+            } => None,
+            Error::Decode { .. } => Some(
+                "Check that the file is an image in a format drmimage can read, such as PNG, \
+                 JPEG, GIF or WebP",
+            ),
+        }
+    }
+
+    /// The exit status of the command line tool. Other errors exit with 1, and invalid arguments
+    /// with 2.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Device { .. } => 3,
+            Error::Master(_) | Error::Permission(_) => 4,
+            Error::Resource(_) => 5,
+            Error::Format(_) => 6,
+            Error::Decode { .. } => 7,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Device { description, .. } => f.write_str(description),
+            Error::Master(_) => f.write_str("Failed to become DRM master"),
+            Error::Permission(description)
+            | Error::Resource(description)
+            | Error::Format(description) => f.write_str(description),
+            Error::Decode { path, .. } => write!(f, "Failed to open {}", path.display()),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Device {
+                source: Some(e), ..
+            } => Some(e),
+            Error::Master(e) => Some(e),
+            Error::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use drm::buffer::DrmFourcc;
+use image::Rgba;
+use std::fmt;
+
+/// A pixel format drmimage can write images in.
+///
+/// All of these are little-endian, even on big-endian architectures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Argb8888,
+    Abgr8888,
+    Xrgb8888,
+    Xbgr8888,
+    Argb2101010,
+    Xrgb2101010,
+    Rgb888,
+    Rgb565,
+}
+
+impl PixelFormat {
+    /// Every format, best first: those that keep transparency, then those that keep all eight
+    /// bits of every channel.
+    pub const PREFERENCE: [PixelFormat; 8] = [
+        PixelFormat::Argb8888,
+        PixelFormat::Abgr8888,
+        PixelFormat::Xrgb8888,
+        PixelFormat::Xbgr8888,
+        PixelFormat::Argb2101010,
+        PixelFormat::Xrgb2101010,
+        PixelFormat::Rgb888,
+        PixelFormat::Rgb565,
+    ];
+
+    /// The best format out of `formats`, a list of fourcc codes such as the one a plane
+    /// advertises.
+    pub fn best(formats: &[u32]) -> Option<PixelFormat> {
+        PixelFormat::PREFERENCE
+            .into_iter()
+            .find(|format| formats.contains(&(format.fourcc() as u32)))
+    }
+
+    pub fn fourcc(self) -> DrmFourcc {
+        match self {
+            PixelFormat::Argb8888 => DrmFourcc::Argb8888,
+            PixelFormat::Abgr8888 => DrmFourcc::Abgr8888,
+            PixelFormat::Xrgb8888 => DrmFourcc::Xrgb8888,
+            PixelFormat::Xbgr8888 => DrmFourcc::Xbgr8888,
+            PixelFormat::Argb2101010 => DrmFourcc::Argb2101010,
+            PixelFormat::Xrgb2101010 => DrmFourcc::Xrgb2101010,
+            PixelFormat::Rgb888 => DrmFourcc::Rgb888,
+            PixelFormat::Rgb565 => DrmFourcc::Rgb565,
+        }
+    }
+
+    /// The format with the given fourcc code, if drmimage knows it.
+    pub fn from_fourcc(fourcc: DrmFourcc) -> Option<PixelFormat> {
+        PixelFormat::PREFERENCE
+            .into_iter()
+            .find(|format| format.fourcc() == fourcc)
+    }
+
+    /// Bits per pixel.
+    pub fn bpp(self) -> u32 {
+        match self {
+            PixelFormat::Rgb888 => 24,
+            PixelFormat::Rgb565 => 16,
+            _ => 32,
+        }
+    }
+
+    /// The colour depth the legacy ADDFB ioctl identifies this format by, together with
+    /// [`Self::bpp`]. Only some formats can be described that way.
+    pub fn legacy_depth(self) -> Option<u32> {
+        match self {
+            PixelFormat::Argb8888 => Some(32),
+            PixelFormat::Xrgb8888 | PixelFormat::Rgb888 => Some(24),
+            PixelFormat::Xrgb2101010 => Some(30),
+            PixelFormat::Rgb565 => Some(16),
+            PixelFormat::Abgr8888 | PixelFormat::Xbgr8888 | PixelFormat::Argb2101010 => None,
+        }
+    }
+
+    /// The format the legacy GETFB ioctl describes by its depth and bpp.
+    pub fn from_legacy(depth: u32, bpp: u32) -> Option<PixelFormat> {
+        PixelFormat::PREFERENCE
+            .into_iter()
+            .find(|format| format.legacy_depth() == Some(depth) && format.bpp() == bpp)
+    }
+
+    /// Whether the format keeps the alpha channel.
+    pub fn has_alpha(self) -> bool {
+        matches!(
+            self,
+            PixelFormat::Argb8888 | PixelFormat::Abgr8888 | PixelFormat::Argb2101010
+        )
+    }
+
+    /// Writes `pixel` to the start of `out`, which has to be at least [`Self::bpp`] bits long.
+    pub fn pack(self, Rgba([r, g, b, a]): Rgba<u8>, out: &mut [u8]) {
+        // Padding bits are set, in case the hardware looks at them anyway
+        let wide = |c: u8| (u32::from(c) << 2) | (u32::from(c) >> 6);
+        match self {
+            PixelFormat::Argb8888 => out[..4].copy_from_slice(&[b, g, r, a]),
+            PixelFormat::Abgr8888 => out[..4].copy_from_slice(&[r, g, b, a]),
+            PixelFormat::Xrgb8888 => out[..4].copy_from_slice(&[b, g, r, 0xff]),
+            PixelFormat::Xbgr8888 => out[..4].copy_from_slice(&[r, g, b, 0xff]),
+            PixelFormat::Argb2101010 | PixelFormat::Xrgb2101010 => {
+                let a = if self == PixelFormat::Argb2101010 {
+                    u32::from(a >> 6)
+                } else {
+                    0b11
+                };
+                let pixel = a << 30 | wide(r) << 20 | wide(g) << 10 | wide(b);
+                out[..4].copy_from_slice(&pixel.to_le_bytes());
+            }
+            PixelFormat::Rgb888 => out[..3].copy_from_slice(&[b, g, r]),
+            PixelFormat::Rgb565 => {
+                let pixel = u16::from(r >> 3) << 11 | u16::from(g >> 2) << 5 | u16::from(b >> 3);
+                out[..2].copy_from_slice(&pixel.to_le_bytes());
+            }
+        }
+    }
+
+    /// Reads the pixel at the start of `bytes`, the inverse of [`Self::pack`]. Formats without
+    /// alpha give opaque pixels.
+    pub fn unpack(self, bytes: &[u8]) -> Rgba<u8> {
+        let narrow = |c: u32| ((c & 0x3ff) >> 2) as u8;
+        let expand =
+            |c: u16, bits: u32| ((u32::from(c) * 255 + (1 << bits) / 2) / ((1 << bits) - 1)) as u8;
+        match self {
+            PixelFormat::Argb8888 => Rgba([bytes[2], bytes[1], bytes[0], bytes[3]]),
+            PixelFormat::Abgr8888 => Rgba([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            PixelFormat::Xrgb8888 => Rgba([bytes[2], bytes[1], bytes[0], 0xff]),
+            PixelFormat::Xbgr8888 => Rgba([bytes[0], bytes[1], bytes[2], 0xff]),
+            PixelFormat::Argb2101010 | PixelFormat::Xrgb2101010 => {
+                let pixel = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+                let a = if self == PixelFormat::Argb2101010 {
+                    (pixel >> 30) as u8 * 0x55
+                } else {
+                    0xff
+                };
+                Rgba([narrow(pixel >> 20), narrow(pixel >> 10), narrow(pixel), a])
+            }
+            PixelFormat::Rgb888 => Rgba([bytes[2], bytes[1], bytes[0], 0xff]),
+            PixelFormat::Rgb565 => {
+                let pixel = u16::from_le_bytes([bytes[0], bytes[1]]);
+                Rgba([
+                    expand(pixel >> 11, 5),
+                    expand((pixel >> 5) & 0x3f, 6),
+                    expand(pixel & 0x1f, 5),
+                    0xff,
+                ])
+            }
+        }
+    }
+}
+
+/// Shows the fourcc code, such as `XR24`.
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.fourcc())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(format: PixelFormat, pixel: Rgba<u8>) -> Rgba<u8> {
+        let mut bytes = [0; 4];
+        format.pack(pixel, &mut bytes);
+        format.unpack(&bytes)
+    }
+
+    /// A spread of colours, including the extremes of every channel.
+    fn colours() -> impl Iterator<Item = Rgba<u8>> {
+        (0..=255u8)
+            .step_by(5)
+            .map(|c| Rgba([c, 255 - c, c.wrapping_mul(7), c / 3]))
+            .chain([Rgba([0, 0, 0, 0]), Rgba([255, 255, 255, 255])])
+    }
+
+    #[test]
+    fn eight_bit_formats_round_trip_exactly() {
+        for pixel in colours() {
+            let opaque = Rgba([pixel[0], pixel[1], pixel[2], 255]);
+            assert_eq!(round_trip(PixelFormat::Argb8888, pixel), pixel);
+            assert_eq!(round_trip(PixelFormat::Abgr8888, pixel), pixel);
+            assert_eq!(round_trip(PixelFormat::Xrgb8888, pixel), opaque);
+            assert_eq!(round_trip(PixelFormat::Xbgr8888, pixel), opaque);
+            assert_eq!(round_trip(PixelFormat::Rgb888, pixel), opaque);
+        }
+    }
+
+    #[test]
+    fn ten_bit_formats_keep_colours_and_quantize_alpha() {
+        for pixel in colours() {
+            let Rgba([r, g, b, a]) = pixel;
+            assert_eq!(
+                round_trip(PixelFormat::Xrgb2101010, pixel),
+                Rgba([r, g, b, 255])
+            );
+            assert_eq!(
+                round_trip(PixelFormat::Argb2101010, pixel),
+                Rgba([r, g, b, (a >> 6) * 0x55])
+            );
+        }
+    }
+
+    #[test]
+    fn rgb565_round_trips_within_its_precision() {
+        for pixel in colours() {
+            let unpacked = round_trip(PixelFormat::Rgb565, pixel);
+            for (channel, bits) in [(0, 5), (1, 6), (2, 5)] {
+                let error = unpacked[channel].abs_diff(pixel[channel]);
+                assert!(error < 1 << (8 - bits), "{pixel:?} became {unpacked:?}");
+            }
+            assert_eq!(unpacked[3], 255);
+            // Packing what was unpacked changes nothing more
+            assert_eq!(round_trip(PixelFormat::Rgb565, unpacked), unpacked);
+        }
+        assert_eq!(
+            round_trip(PixelFormat::Rgb565, Rgba([255, 255, 255, 255])),
+            Rgba([255, 255, 255, 255])
+        );
+    }
+
+    #[test]
+    fn packs_little_endian() {
+        let mut bytes = [0; 4];
+        PixelFormat::Argb8888.pack(Rgba([1, 2, 3, 4]), &mut bytes);
+        assert_eq!(bytes, [3, 2, 1, 4]);
+        PixelFormat::Rgb565.pack(Rgba([255, 0, 0, 255]), &mut bytes);
+        assert_eq!(bytes[..2], [0x00, 0xf8]);
+        PixelFormat::Rgb888.pack(Rgba([1, 2, 3, 4]), &mut bytes);
+        assert_eq!(bytes[..3], [3, 2, 1]);
+    }
+
+    #[test]
+    fn fills_padding_and_widens_ten_bit_channels() {
+        let mut bytes = [0; 4];
+        PixelFormat::Xbgr8888.pack(Rgba([1, 2, 3, 4]), &mut bytes);
+        assert_eq!(bytes, [1, 2, 3, 0xff]);
+        PixelFormat::Xrgb2101010.pack(Rgba([255, 0, 0, 0]), &mut bytes);
+        assert_eq!(u32::from_le_bytes(bytes), 0b11 << 30 | 0x3ff << 20);
+        PixelFormat::Argb2101010.pack(Rgba([0, 0, 0x80, 0x80]), &mut bytes);
+        assert_eq!(u32::from_le_bytes(bytes), 0b10 << 30 | 0x202);
+    }
+
+    #[test]
+    fn legacy_depth_and_bpp_identify_the_format() {
+        for format in PixelFormat::PREFERENCE {
+            if let Some(depth) = format.legacy_depth() {
+                assert_eq!(PixelFormat::from_legacy(depth, format.bpp()), Some(format));
+            }
+        }
+        assert_eq!(PixelFormat::from_legacy(8, 8), None);
+    }
+
+    #[test]
+    fn displays_the_fourcc_code() {
+        assert_eq!(PixelFormat::Argb8888.to_string(), "AR24");
+        assert_eq!(PixelFormat::Rgb565.to_string(), "RG16");
+    }
+}
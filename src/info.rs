@@ -0,0 +1,735 @@
+//! A snapshot of everything a card exposes: its connectors, encoders, CRTCs and planes.
+
+use crate::{Card, PlaneCapabilities};
+use drm::control::{connector, property, Device as _, Mode, ModeTypeFlags, ResourceHandle};
+use drm::Device as _;
+use eyre::Result;
+use serde::Serialize;
+use std::{fmt, path::Path};
+
+/// The topology of a card, as shown by `drmimage info`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    /// The device node, such as `/dev/dri/card0`.
+    pub path: String,
+    pub driver: DriverInfo,
+    pub connectors: Vec<ConnectorInfo>,
+    pub encoders: Vec<EncoderInfo>,
+    pub crtcs: Vec<CrtcInfo>,
+    pub planes: Vec<PlaneInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DriverInfo {
+    pub name: String,
+    /// Major, minor and patch level, such as `1.6.0`.
+    pub version: String,
+    pub date: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectorInfo {
+    pub id: u32,
+    /// The name of the connector, such as `HDMI-A-1`.
+    pub name: String,
+    /// `connected`, `disconnected` or `unknown`.
+    pub state: String,
+    /// The name of the connected monitor, from its EDID.
+    pub monitor: Option<String>,
+    /// The physical size of the display in millimetres.
+    pub size_mm: Option<(u32, u32)>,
+    pub encoders: Vec<u32>,
+    pub current_encoder: Option<u32>,
+    pub modes: Vec<ModeInfo>,
+    pub properties: Vec<PropertyInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModeInfo {
+    pub name: String,
+    pub width: u16,
+    pub height: u16,
+    /// The vertical refresh rate in Hz.
+    pub refresh: u32,
+    pub preferred: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncoderInfo {
+    pub id: u32,
+    /// The kind of signal the encoder produces, such as `TMDS` or `Virtual`.
+    pub kind: String,
+    pub crtc: Option<u32>,
+    pub possible_crtcs: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrtcInfo {
+    pub id: u32,
+    /// The mode the CRTC is driven with, or `None` if it is off.
+    pub mode: Option<ModeInfo>,
+    pub framebuffer: Option<u32>,
+    pub position: (u32, u32),
+    pub properties: Vec<PropertyInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaneInfo {
+    pub id: u32,
+    /// `overlay`, `primary` or `cursor`.
+    pub kind: String,
+    pub crtc: Option<u32>,
+    pub framebuffer: Option<u32>,
+    pub possible_crtcs: Vec<u32>,
+    /// The fourcc codes of the pixel formats the plane can scan out, such as `XR24`.
+    pub formats: Vec<String>,
+    /// The layouts each format can be scanned out in, from the `IN_FORMATS` property. Empty if
+    /// the driver doesn't support modifiers.
+    pub modifiers: Vec<FormatModifiers>,
+    pub properties: Vec<PropertyInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FormatModifiers {
+    pub format: String,
+    /// The modifiers as hexadecimal strings such as `0x0100000000000001`, since they don't fit
+    /// in the integers of every JSON parser.
+    pub modifiers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PropertyInfo {
+    pub name: String,
+    pub value: PropertyValue,
+    /// The name of the value, for enum properties.
+    pub value_name: Option<String>,
+}
+
+/// The value of a property, decoded as signed for properties with a signed range.
+///
+/// In JSON, values are always decimal strings, since 64-bit integers don't fit in the numbers
+/// of every JSON parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyValue {
+    Unsigned(u64),
+    Signed(i64),
+}
+
+impl Serialize for PropertyValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyValue::Unsigned(value) => write!(f, "{value}"),
+            PropertyValue::Signed(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// The version of the JSON schema, bumped whenever a field is removed or changes meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+impl DeviceInfo {
+    /// Reads the topology of `card`, which was opened from `path`.
+    pub fn read(card: &Card, path: &Path) -> Result<DeviceInfo> {
+        let driver = card.get_driver()?;
+        let (major, minor, patch) = driver.version;
+        let driver = DriverInfo {
+            name: driver.name().to_string_lossy().into_owned(),
+            version: format!("{major}.{minor}.{patch}"),
+            date: driver.date().to_string_lossy().into_owned(),
+            description: driver.description().to_string_lossy().into_owned(),
+        };
+        let resources = card.resource_handles()?;
+        let mut connectors = Vec::new();
+        for &handle in resources.connectors() {
+            let connector = card.get_connector(handle, false)?;
+            let monitor = match card.property_values(handle)?.get("EDID") {
+                Some(&blob) if blob != 0 => monitor_name(&card.get_property_blob(blob)?),
+                _ => None,
+            };
+            connectors.push(ConnectorInfo {
+                id: id(handle),
+                name: connector.to_string(),
+                state: match connector.state() {
+                    connector::State::Connected => "connected",
+                    connector::State::Disconnected => "disconnected",
+                    connector::State::Unknown => "unknown",
+                }
+                .to_owned(),
+                monitor,
+                size_mm: connector.size(),
+                encoders: connector.encoders().iter().copied().map(id).collect(),
+                current_encoder: connector.current_encoder().map(id),
+                modes: connector.modes().iter().map(ModeInfo::from).collect(),
+                properties: properties(card, handle)?,
+            });
+        }
+        let mut encoders = Vec::new();
+        for &handle in resources.encoders() {
+            let encoder = card.get_encoder(handle)?;
+            encoders.push(EncoderInfo {
+                id: id(handle),
+                kind: format!("{:?}", encoder.kind()),
+                crtc: encoder.crtc().map(id),
+                possible_crtcs: resources
+                    .filter_crtcs(encoder.possible_crtcs())
+                    .into_iter()
+                    .map(id)
+                    .collect(),
+            });
+        }
+        let mut crtcs = Vec::new();
+        for &handle in resources.crtcs() {
+            let crtc = card.get_crtc(handle)?;
+            crtcs.push(CrtcInfo {
+                id: id(handle),
+                mode: crtc.mode().as_ref().map(ModeInfo::from),
+                framebuffer: crtc.framebuffer().map(id),
+                position: crtc.position(),
+                properties: properties(card, handle)?,
+            });
+        }
+        let mut planes = Vec::new();
+        for handle in card.plane_handles()? {
+            let plane = card.get_plane(handle)?;
+            let modifiers = match card.property_values(handle)?.get("IN_FORMATS") {
+                Some(&blob) if blob != 0 => format_modifiers(&card.get_property_blob(blob)?),
+                _ => Vec::new(),
+            };
+            planes.push(PlaneInfo {
+                id: id(handle),
+                kind: PlaneCapabilities::read(card, &plane)?.kind.to_string(),
+                crtc: plane.crtc().map(id),
+                framebuffer: plane.framebuffer().map(id),
+                possible_crtcs: resources
+                    .filter_crtcs(plane.possible_crtcs())
+                    .into_iter()
+                    .map(id)
+                    .collect(),
+                formats: plane.formats().iter().map(|&f| fourcc_name(f)).collect(),
+                modifiers,
+                properties: properties(card, handle)?,
+            });
+        }
+        Ok(DeviceInfo {
+            path: path.display().to_string(),
+            driver,
+            connectors,
+            encoders,
+            crtcs,
+            planes,
+        })
+    }
+}
+
+/// Serializes the topology of `cards` as pretty-printed JSON, along with a `schema_version`
+/// field.
+pub fn to_json(cards: &[DeviceInfo]) -> serde_json::Result<String> {
+    #[derive(Serialize)]
+    struct Document<'a> {
+        schema_version: u32,
+        cards: &'a [DeviceInfo],
+    }
+    serde_json::to_string_pretty(&Document {
+        schema_version: SCHEMA_VERSION,
+        cards,
+    })
+}
+
+impl From<&Mode> for ModeInfo {
+    fn from(mode: &Mode) -> ModeInfo {
+        let (width, height) = mode.size();
+        ModeInfo {
+            name: mode.name().to_string_lossy().into_owned(),
+            width,
+            height,
+            refresh: mode.vrefresh(),
+            preferred: mode.mode_type().contains(ModeTypeFlags::PREFERRED),
+        }
+    }
+}
+
+fn id<T: ResourceHandle>(handle: T) -> u32 {
+    handle.into()
+}
+
+/// Reads the properties of `handle`, sorted by name.
+fn properties<T: ResourceHandle>(card: &Card, handle: T) -> Result<Vec<PropertyInfo>> {
+    let mut properties: Vec<_> = card
+        .properties(handle)?
+        .into_iter()
+        .map(|(name, (info, value))| {
+            let value_type = info.value_type();
+            let value_name = match &value_type {
+                property::ValueType::Enum(values) => values
+                    .get_value_from_raw_value(value)
+                    .map(|v| v.name().to_string_lossy().into_owned()),
+                _ => None,
+            };
+            PropertyInfo {
+                name,
+                value: match value_type {
+                    property::ValueType::SignedRange(..) => PropertyValue::Signed(value as i64),
+                    _ => PropertyValue::Unsigned(value),
+                },
+                value_name,
+            }
+        })
+        .collect();
+    properties.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(properties)
+}
+
+/// Spells out a fourcc code, such as `XR24` for Xrgb8888.
+fn fourcc_name(fourcc: u32) -> String {
+    fourcc
+        .to_le_bytes()
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() { b as char } else { '?' })
+        .collect()
+}
+
+/// Parses an `IN_FORMATS` blob, a `drm_format_modifier_blob` header followed by a list of
+/// formats and a list of modifiers, each with a bitmask of the formats it applies to.
+fn format_modifiers(blob: &[u8]) -> Vec<FormatModifiers> {
+    let u32_at = |offset: usize| {
+        let bytes = blob.get(offset..offset + 4)?;
+        Some(u32::from_ne_bytes(bytes.try_into().unwrap()) as usize)
+    };
+    let u64_at = |offset: usize| {
+        let bytes = blob.get(offset..offset + 8)?;
+        Some(u64::from_ne_bytes(bytes.try_into().unwrap()))
+    };
+    let parse = || {
+        let (count_formats, formats_offset) = (u32_at(8)?, u32_at(12)?);
+        let (count_modifiers, modifiers_offset) = (u32_at(16)?, u32_at(20)?);
+        let mut result: Vec<FormatModifiers> = (0..count_formats)
+            .map(|i| {
+                Some(FormatModifiers {
+                    format: fourcc_name(u32_at(formats_offset + i * 4)? as u32),
+                    modifiers: Vec::new(),
+                })
+            })
+            .collect::<Option<_>>()?;
+        for i in 0..count_modifiers {
+            // struct drm_format_modifier { u64 formats; u32 offset; u32 pad; u64 modifier; }
+            let entry = modifiers_offset + i * 24;
+            let (formats, offset, modifier) =
+                (u64_at(entry)?, u32_at(entry + 8)?, u64_at(entry + 16)?);
+            for bit in 0..64 {
+                if formats & (1 << bit) != 0 {
+                    let format = result.get_mut(offset + bit)?;
+                    format.modifiers.push(format!("{modifier:#018x}"));
+                }
+            }
+        }
+        Some(result)
+    };
+    parse().unwrap_or_default()
+}
+
+/// Finds the monitor name descriptor in the base block of an EDID.
+fn monitor_name(edid: &[u8]) -> Option<String> {
+    (54..126).step_by(18).find_map(|start| {
+        let descriptor = edid.get(start..start + 18)?;
+        if descriptor[..3] != [0, 0, 0] || descriptor[3] != 0xfc {
+            return None;
+        }
+        let name = &descriptor[5..];
+        let end = name.iter().position(|&b| b == b'\n').unwrap_or(name.len());
+        Some(String::from_utf8_lossy(&name[..end]).trim().to_owned())
+    })
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let driver = &self.driver;
+        writeln!(f, "Card {}", self.path)?;
+        writeln!(
+            f,
+            "Driver: {} {} ({}), {}",
+            driver.name, driver.version, driver.date, driver.description
+        )?;
+        for connector in &self.connectors {
+            write!(
+                f,
+                "\nConnector {} {}: {}",
+                connector.id, connector.name, connector.state
+            )?;
+            if let Some(monitor) = &connector.monitor {
+                write!(f, ", monitor \"{monitor}\"")?;
+            }
+            if let Some((width, height)) = connector.size_mm {
+                write!(f, ", {width}x{height} mm")?;
+            }
+            writeln!(f)?;
+            writeln!(
+                f,
+                "  Encoders: {}{}",
+                list(&connector.encoders),
+                match connector.current_encoder {
+                    Some(encoder) => format!(" (current {encoder})"),
+                    None => String::new(),
+                }
+            )?;
+            if !connector.modes.is_empty() {
+                writeln!(f, "  Modes:")?;
+                for mode in &connector.modes {
+                    writeln!(f, "    {mode}")?;
+                }
+            }
+            write_properties(f, &connector.properties)?;
+        }
+        writeln!(f)?;
+        for encoder in &self.encoders {
+            writeln!(
+                f,
+                "Encoder {}: {}, CRTC {}, possible CRTCs {}",
+                encoder.id,
+                encoder.kind,
+                optional(encoder.crtc),
+                list(&encoder.possible_crtcs)
+            )?;
+        }
+        for crtc in &self.crtcs {
+            write!(f, "\nCRTC {}: ", crtc.id)?;
+            match &crtc.mode {
+                Some(mode) => writeln!(
+                    f,
+                    "{mode}, framebuffer {} at {},{}",
+                    optional(crtc.framebuffer),
+                    crtc.position.0,
+                    crtc.position.1
+                )?,
+                None => writeln!(f, "off")?,
+            }
+            write_properties(f, &crtc.properties)?;
+        }
+        for plane in &self.planes {
+            writeln!(
+                f,
+                "\nPlane {}: {}, CRTC {}, framebuffer {}, possible CRTCs {}",
+                plane.id,
+                plane.kind,
+                optional(plane.crtc),
+                optional(plane.framebuffer),
+                list(&plane.possible_crtcs)
+            )?;
+            writeln!(f, "  Formats: {}", plane.formats.join(" "))?;
+            write_properties(f, &plane.properties)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ModeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}@{}", self.width, self.height, self.refresh)?;
+        if self.name != format!("{}x{}", self.width, self.height) {
+            write!(f, " \"{}\"", self.name)?;
+        }
+        if self.preferred {
+            write!(f, " preferred")?;
+        }
+        Ok(())
+    }
+}
+
+fn write_properties(f: &mut fmt::Formatter<'_>, properties: &[PropertyInfo]) -> fmt::Result {
+    if properties.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "  Properties:")?;
+    for property in properties {
+        write!(f, "    {} = {}", property.name, property.value)?;
+        if let Some(name) = &property.value_name {
+            write!(f, " ({name})")?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+fn list(ids: &[u32]) -> String {
+    if ids.is_empty() {
+        return "none".to_owned();
+    }
+    let ids: Vec<String> = ids.iter().map(u32::to_string).collect();
+    ids.join(", ")
+}
+
+fn optional(id: Option<u32>) -> String {
+    id.map_or_else(|| "none".to_owned(), |id| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn property(name: &str, value: PropertyValue) -> PropertyInfo {
+        PropertyInfo {
+            name: name.to_owned(),
+            value,
+            value_name: None,
+        }
+    }
+
+    fn device() -> DeviceInfo {
+        let mode = ModeInfo {
+            name: "1920x1080".to_owned(),
+            width: 1920,
+            height: 1080,
+            refresh: 60,
+            preferred: true,
+        };
+        DeviceInfo {
+            path: "/dev/dri/card0".to_owned(),
+            driver: DriverInfo {
+                name: "vkms".to_owned(),
+                version: "1.0.0".to_owned(),
+                date: "20180514".to_owned(),
+                description: "Virtual Kernel Mode Setting".to_owned(),
+            },
+            connectors: vec![ConnectorInfo {
+                id: 35,
+                name: "Virtual-1".to_owned(),
+                state: "connected".to_owned(),
+                monitor: None,
+                size_mm: Some((0, 0)),
+                encoders: vec![34],
+                current_encoder: Some(34),
+                modes: vec![mode.clone()],
+                properties: vec![PropertyInfo {
+                    name: "DPMS".to_owned(),
+                    value: PropertyValue::Unsigned(0),
+                    value_name: Some("On".to_owned()),
+                }],
+            }],
+            encoders: vec![EncoderInfo {
+                id: 34,
+                kind: "Virtual".to_owned(),
+                crtc: Some(33),
+                possible_crtcs: vec![33],
+            }],
+            crtcs: vec![CrtcInfo {
+                id: 33,
+                mode: Some(mode),
+                framebuffer: None,
+                position: (0, 0),
+                properties: Vec::new(),
+            }],
+            planes: vec![PlaneInfo {
+                id: 31,
+                kind: "overlay".to_owned(),
+                crtc: None,
+                framebuffer: None,
+                possible_crtcs: vec![33],
+                formats: vec!["XR24".to_owned()],
+                modifiers: vec![FormatModifiers {
+                    format: "XR24".to_owned(),
+                    modifiers: vec!["0x0000000000000000".to_owned()],
+                }],
+                properties: vec![
+                    property("CRTC_X", PropertyValue::Signed(-100)),
+                    property("CRTC_Y", PropertyValue::Signed(i64::MIN)),
+                    property("FB_ID", PropertyValue::Unsigned(u64::MAX)),
+                    property("zpos", PropertyValue::Unsigned(1 << 53)),
+                ],
+            }],
+        }
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<_> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn json_schema() {
+        let json: Value = serde_json::from_str(&to_json(&[device()]).unwrap()).unwrap();
+        assert_eq!(keys(&json), ["cards", "schema_version"]);
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        let card = &json["cards"][0];
+        assert_eq!(
+            keys(card),
+            [
+                "connectors",
+                "crtcs",
+                "driver",
+                "encoders",
+                "path",
+                "planes"
+            ]
+        );
+        assert_eq!(
+            keys(&card["driver"]),
+            ["date", "description", "name", "version"]
+        );
+        assert_eq!(
+            card["connectors"][0],
+            json!({
+                "id": 35,
+                "name": "Virtual-1",
+                "state": "connected",
+                "monitor": null,
+                "size_mm": [0, 0],
+                "encoders": [34],
+                "current_encoder": 34,
+                "modes": [{
+                    "name": "1920x1080",
+                    "width": 1920,
+                    "height": 1080,
+                    "refresh": 60,
+                    "preferred": true,
+                }],
+                "properties": [{ "name": "DPMS", "value": "0", "value_name": "On" }],
+            })
+        );
+        assert_eq!(
+            card["encoders"][0],
+            json!({ "id": 34, "kind": "Virtual", "crtc": 33, "possible_crtcs": [33] })
+        );
+        assert_eq!(
+            keys(&card["crtcs"][0]),
+            ["framebuffer", "id", "mode", "position", "properties"]
+        );
+        assert_eq!(card["crtcs"][0]["position"], json!([0, 0]));
+        let plane = &card["planes"][0];
+        assert_eq!(
+            keys(plane),
+            [
+                "crtc",
+                "formats",
+                "framebuffer",
+                "id",
+                "kind",
+                "modifiers",
+                "possible_crtcs",
+                "properties"
+            ]
+        );
+        assert_eq!(
+            plane["modifiers"],
+            json!([{ "format": "XR24", "modifiers": ["0x0000000000000000"] }])
+        );
+    }
+
+    #[test]
+    fn property_values_stay_exact() {
+        let json: Value = serde_json::from_str(&to_json(&[device()]).unwrap()).unwrap();
+        let values: Vec<&Value> = json["cards"][0]["planes"][0]["properties"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|property| &property["value"])
+            .collect();
+        assert_eq!(
+            values,
+            [
+                &json!("-100"),
+                &json!("-9223372036854775808"),
+                &json!("18446744073709551615"),
+                &json!("9007199254740992"),
+            ]
+        );
+    }
+
+    /// An `IN_FORMATS` blob with `formats`, followed by `modifiers` given as the bitmask of
+    /// formats, the index of the format of the first bit, and the modifier.
+    fn in_formats(formats: &[&[u8; 4]], modifiers: &[(u64, u32, u64)]) -> Vec<u8> {
+        let formats_offset = 24;
+        // Modifiers are aligned to 8 bytes, like the kernel does
+        let modifiers_offset = (formats_offset + formats.len() * 4).next_multiple_of(8);
+        let mut blob = Vec::new();
+        for field in [1, 0, formats.len(), formats_offset, modifiers.len()] {
+            blob.extend((field as u32).to_ne_bytes());
+        }
+        blob.extend((modifiers_offset as u32).to_ne_bytes());
+        for format in formats {
+            blob.extend(*format);
+        }
+        blob.resize(modifiers_offset, 0);
+        for &(mask, offset, modifier) in modifiers {
+            blob.extend(mask.to_ne_bytes());
+            blob.extend(offset.to_ne_bytes());
+            blob.extend([0; 4]);
+            blob.extend(modifier.to_ne_bytes());
+        }
+        blob
+    }
+
+    #[test]
+    fn parses_format_modifiers() {
+        let blob = in_formats(
+            &[b"XR24", b"AR24", b"NV12"],
+            &[(0b111, 0, 0), (0b11, 1, 0x0100000000000001)],
+        );
+        let parsed = format_modifiers(&blob);
+        let modifiers: Vec<(&str, Vec<&str>)> = parsed
+            .iter()
+            .map(|format| {
+                let modifiers = format.modifiers.iter().map(String::as_str).collect();
+                (format.format.as_str(), modifiers)
+            })
+            .collect();
+        assert_eq!(
+            modifiers,
+            [
+                ("XR24", vec!["0x0000000000000000"]),
+                ("AR24", vec!["0x0000000000000000", "0x0100000000000001"]),
+                ("NV12", vec!["0x0000000000000000", "0x0100000000000001"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_broken_format_modifiers() {
+        let blob = in_formats(&[b"XR24", b"AR24"], &[(0b11, 0, 0), (0b1, 1, 1)]);
+        assert_eq!(format_modifiers(&blob).len(), 2);
+        assert!(format_modifiers(&blob[..blob.len() - 1]).is_empty());
+        assert!(format_modifiers(&blob[..20]).is_empty());
+        assert!(format_modifiers(&[]).is_empty());
+        // A modifier for a format past the end of the list
+        let blob = in_formats(&[b"XR24"], &[(0b10, 0, 0)]);
+        assert!(format_modifiers(&blob).is_empty());
+    }
+
+    /// The base block of an EDID with the given display descriptors.
+    fn edid(descriptors: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut edid = vec![0; 54];
+        for &(tag, data) in descriptors {
+            let mut descriptor = vec![0, 0, 0, tag, 0];
+            descriptor.extend(data);
+            descriptor.resize(18, b' ');
+            edid.extend(descriptor);
+        }
+        edid.resize(128, 0);
+        edid
+    }
+
+    #[test]
+    fn finds_monitor_name() {
+        let ranges: &[u8] = &[56, 76, 30, 81, 17];
+        let named = edid(&[(0xfd, ranges), (0xfc, b"DELL U2415\n")]);
+        assert_eq!(monitor_name(&named).as_deref(), Some("DELL U2415"));
+        let long = edid(&[(0xfc, b"THIRTEENCHARS")]);
+        assert_eq!(monitor_name(&long).as_deref(), Some("THIRTEENCHARS"));
+        assert_eq!(monitor_name(&edid(&[(0xfd, ranges)])), None);
+        // The name descriptor is cut off
+        assert_eq!(monitor_name(&named[..80]), None);
+        assert_eq!(monitor_name(&[]), None);
+    }
+}
@@ -0,0 +1,120 @@
+use std::{fmt, str::FromStr};
+
+/// Where each output of a video wall sits within a larger image.
+///
+/// Written as `HDMI-A-1 at 0,0; HDMI-A-2 at 1920,0`, with the positions being the top-left
+/// corner of the output in image pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub placements: Vec<Placement>,
+}
+
+/// The position of a single output within a [`Layout`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// The name of the connector, such as `HDMI-A-1`.
+    pub connector: String,
+    pub position: (u32, u32),
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Layout, String> {
+        let placements = s
+            .split(';')
+            .filter(|placement| !placement.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<Placement>, _>>()?;
+        if placements.is_empty() {
+            return Err("The layout does not contain any outputs".into());
+        }
+        Ok(Layout { placements })
+    }
+}
+
+impl FromStr for Placement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Placement, String> {
+        let Some((connector, position)) = s.trim().split_once(" at ") else {
+            return Err(format!(
+                "Expected `<connector> at <x>,<y>`, got `{}`",
+                s.trim()
+            ));
+        };
+        let Some((x, y)) = position.split_once(',') else {
+            return Err(format!(
+                "Expected a position like `1920,0`, got `{position}`"
+            ));
+        };
+        let coordinate = |c: &str| {
+            c.trim()
+                .parse::<u32>()
+                .map_err(|e| format!("Invalid coordinate `{}`: {e}", c.trim()))
+        };
+        Ok(Placement {
+            connector: connector.trim().to_owned(),
+            position: (coordinate(x)?, coordinate(y)?),
+        })
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, placement) in self.placements.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            let (x, y) = placement.position;
+            write!(f, "{} at {x},{y}", placement.connector)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(connector: &str, position: (u32, u32)) -> Placement {
+        Placement {
+            connector: connector.to_owned(),
+            position,
+        }
+    }
+
+    #[test]
+    fn parses_placements() {
+        let layout: Layout = "HDMI-A-1 at 0,0; HDMI-A-2 at 1920, 0;".parse().unwrap();
+        assert_eq!(
+            layout.placements,
+            [
+                placement("HDMI-A-1", (0, 0)),
+                placement("HDMI-A-2", (1920, 0))
+            ]
+        );
+    }
+
+    #[test]
+    fn display_parses_back() {
+        let layout: Layout = "  DP-1 at 10,20 ;eDP-1 at 0,1080".parse().unwrap();
+        assert_eq!(layout.to_string(), "DP-1 at 10,20; eDP-1 at 0,1080");
+        assert_eq!(layout.to_string().parse(), Ok(layout));
+    }
+
+    #[test]
+    fn rejects_malformed_layouts() {
+        for layout in [
+            "",
+            " ; ",
+            "HDMI-A-1",
+            "HDMI-A-1 at 0",
+            "HDMI-A-1 at -1,0",
+            "HDMI-A-1 at x,0",
+            "HDMI-A-1 at 0,0; DP-1",
+        ] {
+            assert!(layout.parse::<Layout>().is_err(), "{layout:?} was accepted");
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Display an image in the linux console, using DRM and an overlay plane.
+
+mod animation;
+mod atomic;
+mod background;
+mod buffer;
+mod capture;
+mod card;
+mod error;
+mod format;
+pub mod info;
+mod layout;
+mod options;
+mod orientation;
+mod output;
+pub mod pattern;
+mod plane;
+mod scale;
+mod session;
+mod state;
+
+pub use animation::{Animation, Frame};
+pub use background::Background;
+pub use buffer::DumbFramebuffer;
+pub use capture::{capture_connector, capture_plane, capture_writeback, read_framebuffer};
+pub use card::Card;
+pub use error::Error;
+pub use format::PixelFormat;
+pub use layout::{Layout, Placement};
+pub use options::DisplayOptions;
+pub use orientation::{Axis, Orientation};
+pub use output::{find_connector, preferred_mode, Output};
+pub use plane::{PlaneCapabilities, PlaneType};
+pub use scale::{Align, Geometry, ScaleMode};
+pub use session::DisplaySession;
+pub use state::{CrtcState, PlaneState};
@@ -1,128 +1,264 @@
-use drm::buffer::{Buffer, DrmFourcc};
-use drm::control::{connector, crtc, plane, Device as _, ResourceHandles};
-use drm::Device;
-use eyre::{bail, Result};
-use image::Rgba;
-use std::{
-    fs::{File, OpenOptions},
-    io,
-    os::fd::{AsFd, BorrowedFd},
-    path::Path,
+mod cli;
+
+use cli::{CaptureArgs, Cli, Command, DeviceArgs, InfoFormat, ShowArgs};
+use drm::control::Device as _;
+use drmimage::info::{self, DeviceInfo};
+use drmimage::{
+    capture_connector, capture_plane, capture_writeback, pattern, Animation, Card, DisplaySession,
+    Error, Output,
 };
+use eyre::{bail, Result, WrapErr};
+use image::{DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, RgbaImage};
+use signal_hook::{consts::TERM_SIGNALS, iterator::Signals};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::{path::Path, process::ExitCode};
 
-fn display<P: AsRef<Path>>(path: P) -> Result<()> {
-    let Some(card) = Card::find_device() else {
-        bail!("Failed to open any card, terminating")
-    };
-    // Make sure we have master
-    card.acquire_master_lock()?;
-    let resources = card.resource_handles()?;
-    let Some(connector) = resources.connectors().iter().find_map(|&handle| {
-        let connector = card.get_connector(handle, false).ok()?;
-        (connector.state() == connector::State::Connected).then_some(connector)
-    }) else {
-        bail!("Failed to find any connected output");
+fn main() -> ExitCode {
+    let Err(report) = run_command(Cli::parse_with_shorthand().command) else {
+        return ExitCode::SUCCESS;
     };
-    let encoder = card.get_encoder(connector.current_encoder().unwrap())?;
-    let crtc = card.get_crtc(encoder.crtc().unwrap())?;
-    let plane = card.get_crtc_plane(&resources, crtc.handle())?;
-    if !plane
-        .formats()
-        .iter()
-        .copied()
-        .any(|f| f == (DrmFourcc::Argb8888 as u32))
-    {
-        bail!("Failed to find suitable format in plane.");
+    if let Some(error) = report.downcast_ref::<clap::Error>() {
+        error.exit();
     }
-    let picture = image::open(path).unwrap().into_rgba8();
-    let mut buffer = card.create_dumb_buffer(picture.dimensions(), DrmFourcc::Argb8888, 32)?;
-    let buffer_size = buffer.size();
-    {
-        let pitch = buffer.pitch();
-        let mut mapping = card.map_dumb_buffer(&mut buffer)?;
-        for (x, y, &Rgba([r, g, b, a])) in picture.enumerate_pixels() {
-            if x >= buffer_size.0 {
-                continue;
-            }
-            if y >= buffer_size.1 {
-                break;
+    eprintln!("Error: {report:?}");
+    match Error::find(&report) {
+        Some(error) => {
+            if let Some(hint) = error.hint() {
+                eprintln!("\nHint: {hint}");
             }
-            let index = x as usize * 4 + y as usize * pitch as usize;
-            // Note: Argb8888 is always little-endian, even on big-endian architectures
-            mapping[index + 3] = a;
-            mapping[index + 2] = r;
-            mapping[index + 1] = g;
-            mapping[index + 0] = b;
+            ExitCode::from(error.exit_code())
         }
-    }
-    let framebuffer = card.add_framebuffer(&buffer, 32, 32)?;
-    card.set_plane(
-        plane.handle(),
-        crtc.handle(),
-        Some(framebuffer),
-        0,
-        (0, 0, buffer.size().0, buffer.size().1),
-        (0, 0, buffer.size().0 << 16, buffer.size().1 << 16),
-    )?;
-    card.release_master_lock()?;
-    eprintln!("Ctrl+C to quit");
-    loop {
-        std::thread::park();
+        None => ExitCode::FAILURE,
     }
 }
 
-fn main() -> Result<()> {
-    if let Some(path) = std::env::args_os().nth(1) {
-        display(path)?;
-    } else {
-        bail!("Please provide the path to an image as an argument.");
+fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::Show(args) => show(args),
+        Command::Info(args) => info(&args.device, args.format),
+        Command::Capture(args) => capture(args),
+        Command::Pattern(args) => {
+            let card = open_card(&args.device)?;
+            let options = args.output.to_options();
+            let resources = card.resource_handles()?;
+            let (width, height) =
+                Output::find(&card, &resources, options.connector.as_deref())?.view_size();
+            let picture = pattern::color_bars(width, height);
+            run(args.duration, None, || {
+                DisplaySession::with_options(card, &picture, &options)
+            })
+        }
     }
-    Ok(())
 }
 
-struct Card(File);
+fn open_card(args: &DeviceArgs) -> Result<Card> {
+    if let Some(path) = &args.device {
+        return Card::open(path).map_err(|e| {
+            eyre::Report::new(Error::Device {
+                description: format!("Failed to open {}", path.display()),
+                source: Some(e),
+            })
+        });
+    }
+    if let Some(driver) = &args.driver {
+        let Some(card) = Card::find_driver(driver) else {
+            bail!(Error::Device {
+                description: format!("Failed to find a card driven by {driver}"),
+                source: None,
+            });
+        };
+        return Ok(card);
+    }
+    match Card::find_device() {
+        Some(card) => Ok(card),
+        None => bail!(Error::Device {
+            description: "Failed to find a card with a connected output".to_owned(),
+            source: None,
+        }),
+    }
+}
 
-impl Card {
-    fn find_device() -> Option<Card> {
-        (0..=255).find_map(|i| {
-            let path = format!("/dev/dri/card{i}");
-            Card::open(&path)
-                .inspect_err(|e| {
-                    if e.kind() != io::ErrorKind::NotFound {
-                        eprintln!("Failed to open {path}: {e:?}");
-                    }
-                })
-                .ok()
-        })
+fn show(args: ShowArgs) -> Result<()> {
+    let options = args.output.to_options();
+    let images = args.images();
+    if images.iter().all(|(connector, _)| connector.is_none()) {
+        if images.len() > 1 {
+            bail!(Cli::usage_error(
+                "show",
+                "Prefix each image with the connector to show it on, like HDMI-A-1=left.png"
+            ));
+        }
+        let path = &images[0].1;
+        let animation = Animation::open(path).map_err(|source| Error::Decode {
+            path: path.clone(),
+            source,
+        })?;
+        if let Some(animation) = animation {
+            let card = open_card(&args.device)?;
+            return run(args.duration, Some(&animation), || {
+                DisplaySession::with_options(card, &animation.first().picture, &options)
+            });
+        }
+        let picture = load_image(path)?;
+        let card = open_card(&args.device)?;
+        return run(args.duration, None, || {
+            DisplaySession::with_options(card, &picture, &options)
+        });
+    }
+    if images.iter().any(|(connector, _)| connector.is_none()) {
+        bail!(Cli::usage_error(
+            "show",
+            "Either prefix every image with a connector, or show a single image"
+        ));
     }
+    if options.connector.is_some() || options.all_outputs || options.layout.is_some() {
+        bail!(Cli::usage_error(
+            "show",
+            "CONNECTOR=IMAGE cannot be combined with --connector, --all-outputs or --layout"
+        ));
+    }
+    let mut pictures = Vec::new();
+    for (connector, path) in images {
+        pictures.push((connector.unwrap(), load_image(&path)?));
+    }
+    let pictures: Vec<_> = pictures
+        .iter()
+        .map(|(connector, picture)| (connector.as_str(), picture))
+        .collect();
+    let card = open_card(&args.device)?;
+    run(args.duration, None, || {
+        DisplaySession::per_connector(card, &pictures, &options)
+    })
+}
 
-    fn open(path: impl AsRef<Path>) -> io::Result<Card> {
-        Ok(Card(OpenOptions::new().read(true).write(true).open(&path)?))
+/// Loads the image at `path`, turned upright according to its EXIF orientation.
+fn load_image(path: &Path) -> Result<RgbaImage> {
+    let open = || {
+        let mut decoder = ImageReader::open(path)?
+            .with_guessed_format()?
+            .into_decoder()?;
+        let orientation = decoder.orientation()?;
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        image.apply_orientation(orientation);
+        Ok::<_, ImageError>(image)
+    };
+    let image = open().map_err(|source| Error::Decode {
+        path: path.to_owned(),
+        source,
+    })?;
+    Ok(image.into_rgba8())
+}
+
+/// Keeps the session created by `show` alive until a termination signal arrives or `duration`
+/// runs out, playing `animation` on it if given.
+fn run(
+    duration: Option<Duration>,
+    animation: Option<&Animation>,
+    show: impl FnOnce() -> Result<DisplaySession>,
+) -> Result<()> {
+    // Register the handlers before touching the planes, so that they always get restored
+    let mut signals = Signals::new(TERM_SIGNALS)?;
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        signals.forever().next();
+        let _ = sender.send(());
+    });
+    let mut session = show()?;
+    let deadline = duration.map(|duration| Instant::now() + duration);
+    if deadline.is_none() {
+        eprintln!("Ctrl+C to quit");
+    }
+    // Sleeps for `timeout`, or until the end if `None`, and returns whether to carry on
+    let mut stopped = false;
+    let mut wait = |timeout: Option<Duration>| {
+        if stopped {
+            return false;
+        }
+        let left = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        stopped = match timeout.into_iter().chain(left).min() {
+            Some(timeout) => {
+                receiver.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout)
+                    || left == Some(timeout)
+            }
+            None => {
+                let _ = receiver.recv();
+                true
+            }
+        };
+        !stopped
+    };
+    if let Some(animation) = animation {
+        session.play(animation, |delay| wait(Some(delay)))?;
     }
+    wait(None);
+    session.close()
+}
 
-    fn get_crtc_plane(
-        &self,
-        resources: &ResourceHandles,
-        crtc: crtc::Handle,
-    ) -> Result<plane::Info> {
-        for handle in self.plane_handles()? {
-            let plane = self.get_plane(handle)?;
-            if plane.crtc().is_none()
-                && resources
-                    .filter_crtcs(plane.possible_crtcs())
-                    .contains(&crtc)
+/// Prints the topology of the card given by `args`, or of every card if none is given.
+///
+/// Unlike the other commands, cards with nothing connected are included, as finding out why no
+/// output is detected is what `info` is for.
+fn info(args: &DeviceArgs, format: InfoFormat) -> Result<()> {
+    let mut cards = Vec::new();
+    if let Some(path) = &args.device {
+        let card = open_card(args)?;
+        cards.push(DeviceInfo::read(&card, path)?);
+    } else {
+        for (path, card) in Card::open_all() {
+            if args
+                .driver
+                .as_ref()
+                .is_some_and(|driver| card.driver_name().ok().as_ref() != Some(driver))
             {
-                return Ok(plane);
+                continue;
+            }
+            // One broken card shouldn't hide the others
+            match DeviceInfo::read(&card, &path) {
+                Ok(info) => cards.push(info),
+                Err(e) => eprintln!("Failed to read {}: {e:?}", path.display()),
+            }
+        }
+    }
+    if cards.is_empty() {
+        bail!(Error::Device {
+            description: match &args.driver {
+                Some(driver) => format!("Failed to find a card driven by {driver}"),
+                None => "Failed to find any card".to_owned(),
+            },
+            source: None,
+        });
+    }
+    match format {
+        InfoFormat::Text => {
+            for (i, card) in cards.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                print!("{card}");
             }
         }
-        bail!("Failed to find a suitable plane for crtc");
+        InfoFormat::Json => println!("{}", info::to_json(&cards)?),
     }
+    Ok(())
 }
 
-impl AsFd for Card {
-    fn as_fd(&self) -> BorrowedFd<'_> {
-        self.0.as_fd()
-    }
+/// Saves the picture on the chosen connector or plane to a PNG file.
+fn capture(args: CaptureArgs) -> Result<()> {
+    let card = open_card(&args.device)?;
+    let picture = match args.plane {
+        Some(plane) => capture_plane(&card, plane)?,
+        None => {
+            let resources = card.resource_handles()?;
+            let connector = args.connector.as_deref();
+            if args.writeback {
+                capture_writeback(&card, &resources, connector)?
+            } else {
+                capture_connector(&card, &resources, connector)?
+            }
+        }
+    };
+    picture
+        .save_with_format(&args.output, ImageFormat::Png)
+        .wrap_err_with(|| format!("Failed to write {}", args.output.display()))
 }
-impl drm::Device for Card {}
-impl drm::control::Device for Card {}
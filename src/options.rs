@@ -0,0 +1,44 @@
+use crate::{Align, Background, Geometry, Layout, Orientation, ScaleMode};
+use drm::control::plane;
+
+/// Settings controlling where and how a [`DisplaySession`](crate::DisplaySession) shows an image.
+#[derive(Debug, Clone, Default)]
+pub struct DisplayOptions {
+    /// The name of the connector to use, such as `HDMI-A-1`. The first connected one is used if
+    /// this is `None`.
+    pub connector: Option<String>,
+    /// Mirror the image on every connected output, each scaled to its own mode, instead of
+    /// showing it on a single one.
+    pub all_outputs: bool,
+    /// Span the image across several outputs, each showing the part of the image at its position
+    /// in the layout.
+    pub layout: Option<Layout>,
+    /// How the image is sized to the mode. Ignored when spanning a layout.
+    pub scale: ScaleMode,
+    /// Which edges of the screen the image is placed against. Ignored when spanning a layout.
+    pub align: Align,
+    /// Where to put the top-left corner of the image on the screen, overriding `align`. The image
+    /// may hang off the edges of the screen.
+    pub position: Option<(i32, i32)>,
+    /// What to fill the rest of the screen with. If `None`, whatever was on screen before shows
+    /// around the image.
+    pub background: Option<Background>,
+    /// The plane to show the image on, instead of the best one available. Only used when showing
+    /// the image on a single output.
+    pub plane: Option<plane::Handle>,
+    /// How to turn the image. The plane does this in hardware if it can.
+    pub orientation: Orientation,
+}
+
+impl DisplayOptions {
+    /// Where a picture of the given size ends up on a CRTC of the given size, once it has been
+    /// turned according to `orientation`.
+    pub fn geometry(&self, picture_size: (u32, u32), crtc_size: (u32, u32)) -> Geometry {
+        let picture_size = self.orientation.size(picture_size);
+        let geometry = Geometry::new(self.scale, self.align, picture_size, crtc_size);
+        match self.position {
+            Some(position) => geometry.at(position),
+            None => geometry,
+        }
+    }
+}
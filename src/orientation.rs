@@ -0,0 +1,290 @@
+use image::{imageops, RgbaImage};
+use std::{fmt, str::FromStr};
+
+/// The values of the `rotation` plane property.
+const DRM_ROTATE_0: u64 = 1 << 0;
+const DRM_ROTATE_90: u64 = 1 << 1;
+const DRM_ROTATE_180: u64 = 1 << 2;
+const DRM_ROTATE_270: u64 = 1 << 3;
+const DRM_REFLECT_X: u64 = 1 << 4;
+
+/// An axis to reflect a picture along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// Swaps left and right.
+    X,
+    /// Swaps top and bottom.
+    Y,
+}
+
+impl FromStr for Axis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Axis, String> {
+        match s {
+            "x" => Ok(Axis::X),
+            "y" => Ok(Axis::Y),
+            _ => Err(format!("Unknown axis `{s}`, expected x or y")),
+        }
+    }
+}
+
+impl fmt::Display for Axis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Axis::X => "x",
+            Axis::Y => "y",
+        })
+    }
+}
+
+/// How a picture is turned before it is shown: first mirrored left to right if `mirror` is set,
+/// then rotated clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Orientation {
+    quarter_turns: u8,
+    mirror: bool,
+}
+
+impl Orientation {
+    /// Leaves the picture as it is.
+    pub const UPRIGHT: Orientation = Orientation {
+        quarter_turns: 0,
+        mirror: false,
+    };
+
+    /// Rotates the picture clockwise. Returns `None` unless `degrees` is a multiple of 90.
+    pub fn rotate(degrees: u32) -> Option<Orientation> {
+        (degrees % 90 == 0).then_some(Orientation {
+            quarter_turns: (degrees / 90 % 4) as u8,
+            mirror: false,
+        })
+    }
+
+    /// Reflects the picture along `axis`.
+    pub fn reflect(axis: Axis) -> Orientation {
+        Orientation {
+            // Flipping top and bottom is the same as flipping left and right and turning around
+            quarter_turns: match axis {
+                Axis::X => 0,
+                Axis::Y => 2,
+            },
+            mirror: true,
+        }
+    }
+
+    /// Turns the picture like `self` and then like `next`.
+    pub fn then(self, next: Orientation) -> Orientation {
+        // Mirroring after a rotation is the same as mirroring first and rotating the other way
+        let quarter_turns = if next.mirror {
+            4 + next.quarter_turns - self.quarter_turns
+        } else {
+            self.quarter_turns + next.quarter_turns
+        };
+        Orientation {
+            quarter_turns: quarter_turns % 4,
+            mirror: self.mirror != next.mirror,
+        }
+    }
+
+    /// The orientation that turns the picture back.
+    pub fn inverse(self) -> Orientation {
+        if self.mirror {
+            self
+        } else {
+            Orientation {
+                quarter_turns: (4 - self.quarter_turns) % 4,
+                mirror: false,
+            }
+        }
+    }
+
+    pub fn is_upright(self) -> bool {
+        self == Orientation::UPRIGHT
+    }
+
+    /// The size of a picture of the given size once it has been turned.
+    pub fn size(self, (width, height): (u32, u32)) -> (u32, u32) {
+        if self.quarter_turns % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Turns `picture` in software.
+    pub fn apply(self, picture: &RgbaImage) -> RgbaImage {
+        let mirrored;
+        let picture = if self.mirror {
+            mirrored = imageops::flip_horizontal(picture);
+            &mirrored
+        } else {
+            picture
+        };
+        match self.quarter_turns {
+            1 => imageops::rotate90(picture),
+            2 => imageops::rotate180(picture),
+            3 => imageops::rotate270(picture),
+            _ => picture.clone(),
+        }
+    }
+
+    /// The value of the `rotation` plane property that makes the plane turn its framebuffer
+    /// like this. DRM rotates counter-clockwise, and reflects before rotating.
+    pub fn drm_rotation(self) -> u64 {
+        let rotation = match self.quarter_turns {
+            1 => DRM_ROTATE_270,
+            2 => DRM_ROTATE_180,
+            3 => DRM_ROTATE_90,
+            _ => DRM_ROTATE_0,
+        };
+        if self.mirror {
+            rotation | DRM_REFLECT_X
+        } else {
+            rotation
+        }
+    }
+
+    /// Maps a rectangle of the turned picture, given as x, y, width and height in 16.16 fixed
+    /// point, back to the same part of the original picture. `turned_size` is the size of the
+    /// turned picture in pixels.
+    pub fn source_rect(
+        self,
+        (x, y, width, height): (u32, u32, u32, u32),
+        turned_size: (u32, u32),
+    ) -> (u32, u32, u32, u32) {
+        let (x, y, width, height) = self.inverse().turn_rect(
+            (x.into(), y.into(), width.into(), height.into()),
+            (
+                i64::from(turned_size.0) << 16,
+                i64::from(turned_size.1) << 16,
+            ),
+        );
+        (x as u32, y as u32, width as u32, height as u32)
+    }
+
+    /// Where a rectangle, given as x, y, width and height, ends up once the area of the given
+    /// size it lies in has been turned. The rectangle may stick out of the area.
+    pub fn turn_rect(
+        self,
+        (x, y, width, height): (i64, i64, i64, i64),
+        size: (i64, i64),
+    ) -> (i64, i64, i64, i64) {
+        let (x0, y0) = self.turn_point((x, y), size);
+        let (x1, y1) = self.turn_point((x + width, y + height), size);
+        (x0.min(x1), y0.min(y1), (x1 - x0).abs(), (y1 - y0).abs())
+    }
+
+    /// Where `point` of an area of the given size ends up once the area has been turned.
+    fn turn_point(
+        self,
+        (mut x, mut y): (i64, i64),
+        (mut width, mut height): (i64, i64),
+    ) -> (i64, i64) {
+        if self.mirror {
+            x = width - x;
+        }
+        for _ in 0..self.quarter_turns {
+            (x, y) = (height - y, x);
+            (width, height) = (height, width);
+        }
+        (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// Every orientation there is.
+    fn all() -> Vec<Orientation> {
+        let mut all = Vec::new();
+        for degrees in [0, 90, 180, 270] {
+            let rotation = Orientation::rotate(degrees).unwrap();
+            all.push(rotation);
+            all.push(Orientation::reflect(Axis::X).then(rotation));
+        }
+        all
+    }
+
+    /// A picture whose pixels are all different, so that turning it can be followed.
+    fn picture() -> RgbaImage {
+        RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8, y as u8, 0, 255]))
+    }
+
+    #[test]
+    fn rotate_takes_quarter_turns() {
+        assert_eq!(Orientation::rotate(0), Some(Orientation::UPRIGHT));
+        assert_eq!(Orientation::rotate(360), Some(Orientation::UPRIGHT));
+        assert_eq!(Orientation::rotate(45), None);
+        assert_eq!(Orientation::rotate(90).unwrap().size((3, 2)), (2, 3));
+        assert_eq!(Orientation::rotate(180).unwrap().size((3, 2)), (3, 2));
+    }
+
+    #[test]
+    fn reflect_y_flips_top_and_bottom() {
+        let flipped = Orientation::reflect(Axis::Y).apply(&picture());
+        assert_eq!(flipped, imageops::flip_vertical(&picture()));
+    }
+
+    #[test]
+    fn then_matches_applying_one_after_the_other() {
+        for first in all() {
+            for next in all() {
+                assert_eq!(
+                    first.then(next).apply(&picture()),
+                    next.apply(&first.apply(&picture())),
+                    "{first:?} then {next:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_turns_back() {
+        for orientation in all() {
+            assert!(orientation.then(orientation.inverse()).is_upright());
+            assert!(orientation.inverse().then(orientation).is_upright());
+        }
+    }
+
+    #[test]
+    fn drm_rotation_is_counter_clockwise() {
+        assert_eq!(Orientation::UPRIGHT.drm_rotation(), DRM_ROTATE_0);
+        assert_eq!(
+            Orientation::rotate(90).unwrap().drm_rotation(),
+            DRM_ROTATE_270
+        );
+        assert_eq!(
+            Orientation::rotate(270).unwrap().drm_rotation(),
+            DRM_ROTATE_90
+        );
+        assert_eq!(
+            Orientation::reflect(Axis::X).drm_rotation(),
+            DRM_ROTATE_0 | DRM_REFLECT_X
+        );
+        assert_eq!(
+            Orientation::reflect(Axis::Y).drm_rotation(),
+            DRM_ROTATE_180 | DRM_REFLECT_X
+        );
+    }
+
+    #[test]
+    fn source_rect_finds_the_original_pixels() {
+        let picture = picture();
+        for orientation in all() {
+            let turned = orientation.apply(&picture);
+            for (x, y, pixel) in turned.enumerate_pixels() {
+                let (src_x, src_y, width, height) = orientation
+                    .source_rect((x << 16, y << 16, 1 << 16, 1 << 16), turned.dimensions());
+                assert_eq!((width, height), (1 << 16, 1 << 16));
+                assert_eq!(
+                    picture.get_pixel(src_x >> 16, src_y >> 16),
+                    pixel,
+                    "{orientation:?} at {x},{y}"
+                );
+            }
+        }
+    }
+}
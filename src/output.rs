@@ -0,0 +1,276 @@
+use crate::{Card, Error, Orientation, PlaneCapabilities, PlaneType};
+use drm::control::{
+    connector, crtc, plane, property, Device as _, Mode, ModeTypeFlags, ResourceHandles,
+};
+use eyre::{bail, Result, WrapErr};
+
+/// A connected connector together with the CRTC driving it and a plane to draw on.
+pub struct Output {
+    pub connector: connector::Info,
+    pub crtc: crtc::Info,
+    /// The plane to show the image on, or `None` if there is no usable plane and the image has
+    /// to be scanned out by the CRTC itself.
+    pub plane: Option<plane::Info>,
+    /// Whether `plane` is the CRTC's primary plane rather than an overlay.
+    pub primary: bool,
+    /// The mode the CRTC is driven with.
+    pub mode: Mode,
+    /// Whether the CRTC has to be set up with `mode` before the plane can be shown.
+    pub needs_modeset: bool,
+    /// How pictures have to be turned to appear upright, for built-in panels that are mounted
+    /// sideways or upside down.
+    pub panel_orientation: Orientation,
+}
+
+impl Output {
+    /// Picks the connector called `name` (or the first connected one if `None`) and a free plane
+    /// on its CRTC.
+    ///
+    /// If the connector is not currently lit, an unused CRTC it can be driven by is chosen and
+    /// the connector's preferred mode is used.
+    pub fn find(card: &Card, resources: &ResourceHandles, name: Option<&str>) -> Result<Output> {
+        let connector = find_connector(card, resources, name)?;
+        Output::for_connector(card, resources, connector, &[])
+    }
+
+    /// Whether the image has to cover the whole CRTC, because it replaces the primary picture
+    /// rather than being overlaid on top of it.
+    pub fn covers_crtc(&self) -> bool {
+        self.primary || self.plane.is_none()
+    }
+
+    /// Whether the plane can rotate and reflect the image itself.
+    pub fn can_rotate(&self, card: &Card) -> Result<bool> {
+        match &self.plane {
+            Some(plane) => Ok(PlaneCapabilities::read(card, plane)?.rotation),
+            None => Ok(false),
+        }
+    }
+
+    /// The size of the mode, in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        let (width, height) = self.mode.size();
+        (width.into(), height.into())
+    }
+
+    /// The size of the mode as the viewer sees it, which has the width and height swapped on
+    /// panels mounted sideways.
+    pub fn view_size(&self) -> (u32, u32) {
+        self.panel_orientation.size(self.size())
+    }
+
+    /// Picks a CRTC and a free plane for every connected connector.
+    ///
+    /// Connectors cloned onto a CRTC that is already in the list are skipped, as they show the
+    /// same picture anyway.
+    pub fn find_all(card: &Card, resources: &ResourceHandles) -> Result<Vec<Output>> {
+        let mut outputs: Vec<Output> = Vec::new();
+        for &handle in resources.connectors() {
+            let connector = card.get_connector(handle, false)?;
+            if connector.state() != connector::State::Connected {
+                continue;
+            }
+            if let Some((crtc, _)) = active_crtc(card, &connector)? {
+                if outputs.iter().any(|o| o.crtc.handle() == crtc.handle()) {
+                    continue;
+                }
+            }
+            outputs.push(Output::for_connector(card, resources, connector, &outputs)?);
+        }
+        if outputs.is_empty() {
+            bail!(Error::Resource(
+                "Failed to find any connected output".to_owned()
+            ));
+        }
+        Ok(outputs)
+    }
+
+    /// Picks a CRTC and a free plane for each of the connectors called `names`, in order.
+    pub fn find_named(
+        card: &Card,
+        resources: &ResourceHandles,
+        names: &[&str],
+    ) -> Result<Vec<Output>> {
+        let mut outputs: Vec<Output> = Vec::new();
+        for &name in names {
+            if outputs.iter().any(|o| o.connector.to_string() == name) {
+                bail!(Error::Resource(format!(
+                    "Connector {name} is used more than once"
+                )));
+            }
+            let connector = find_connector(card, resources, Some(name))?;
+            outputs.push(Output::for_connector(card, resources, connector, &outputs)?);
+        }
+        Ok(outputs)
+    }
+
+    /// Picks the CRTC and a free plane for driving `connector`, leaving alone the CRTCs and planes
+    /// already claimed by `taken`.
+    pub fn for_connector(
+        card: &Card,
+        resources: &ResourceHandles,
+        connector: connector::Info,
+        taken: &[Output],
+    ) -> Result<Output> {
+        let (crtc, mode, needs_modeset) = match active_crtc(card, &connector)? {
+            Some((crtc, mode)) => (crtc, mode, false),
+            None => {
+                let crtc = Output::free_crtc(card, resources, &connector, taken)?;
+                let mode = preferred_mode(&connector).ok_or_else(|| {
+                    Error::Resource(format!("Connector {connector} has no modes"))
+                })?;
+                (crtc, mode, true)
+            }
+        };
+        let taken_planes: Vec<_> = taken
+            .iter()
+            .filter_map(|o| o.plane.as_ref().map(|plane| plane.handle()))
+            .collect();
+        let plane = card.get_crtc_plane(resources, crtc.handle(), &taken_planes)?;
+        let primary = match &plane {
+            Some(plane) => card.plane_type(plane.handle())? == PlaneType::Primary,
+            None => false,
+        };
+        let panel_orientation = panel_orientation(card, &connector)?;
+        Ok(Output {
+            connector,
+            crtc,
+            plane,
+            primary,
+            mode,
+            needs_modeset,
+            panel_orientation,
+        })
+    }
+
+    /// Uses the plane `handle` instead of the one picked automatically.
+    pub fn with_plane(
+        self,
+        card: &Card,
+        resources: &ResourceHandles,
+        handle: plane::Handle,
+    ) -> Result<Output> {
+        let id = u32::from(handle);
+        let plane = card
+            .get_plane(handle)
+            .wrap_err_with(|| format!("Failed to find plane {id}"))?;
+        let capabilities = PlaneCapabilities::read(card, &plane)?;
+        if !capabilities.usable_on(resources, &plane, self.crtc.handle()) {
+            bail!(Error::Resource(format!(
+                "Plane {id} can't be used on output {}",
+                self.connector
+            )));
+        }
+        Ok(Output {
+            plane: Some(plane),
+            primary: capabilities.kind == PlaneType::Primary,
+            ..self
+        })
+    }
+
+    /// Finds an inactive CRTC that one of the connector's encoders can be routed to.
+    fn free_crtc(
+        card: &Card,
+        resources: &ResourceHandles,
+        connector: &connector::Info,
+        taken: &[Output],
+    ) -> Result<crtc::Info> {
+        for &encoder in connector.encoders() {
+            let encoder = card.get_encoder(encoder)?;
+            for crtc in resources.filter_crtcs(encoder.possible_crtcs()) {
+                let crtc = card.get_crtc(crtc)?;
+                if crtc.mode().is_none() && !taken.iter().any(|o| o.crtc.handle() == crtc.handle())
+                {
+                    return Ok(crtc);
+                }
+            }
+        }
+        bail!(Error::Resource(format!(
+            "Failed to find a free CRTC for connector {connector}"
+        )));
+    }
+}
+
+/// The CRTC currently lighting `connector` and the mode it is driven with, if any.
+pub(crate) fn active_crtc(
+    card: &Card,
+    connector: &connector::Info,
+) -> Result<Option<(crtc::Info, Mode)>> {
+    let Some(encoder) = connector.current_encoder() else {
+        return Ok(None);
+    };
+    let Some(crtc) = card.get_encoder(encoder)?.crtc() else {
+        return Ok(None);
+    };
+    let crtc = card.get_crtc(crtc)?;
+    Ok(crtc.mode().map(|mode| (crtc, mode)))
+}
+
+/// The mode the connector reports as preferred, or its first mode if none is.
+pub fn preferred_mode(connector: &connector::Info) -> Option<Mode> {
+    let modes = connector.modes();
+    modes
+        .iter()
+        .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+        .or(modes.first())
+        .copied()
+}
+
+/// Finds the connector called `name`, such as `HDMI-A-1`, or the first connected one if `None`.
+///
+/// The error lists the names of all connectors when the requested one does not exist.
+pub fn find_connector(
+    card: &Card,
+    resources: &ResourceHandles,
+    name: Option<&str>,
+) -> Result<connector::Info> {
+    let connectors = resources
+        .connectors()
+        .iter()
+        .map(|&handle| card.get_connector(handle, false))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(name) = name else {
+        return connectors
+            .into_iter()
+            .find(|connector| connector.state() == connector::State::Connected)
+            .ok_or_else(|| {
+                Error::Resource("Failed to find any connected output".to_owned()).into()
+            });
+    };
+    let names: Vec<String> = connectors.iter().map(|c| c.to_string()).collect();
+    let Some(connector) = connectors.into_iter().find(|c| c.to_string() == name) else {
+        bail!(Error::Resource(format!(
+            "Failed to find connector {name}, available connectors: {}",
+            names.join(", ")
+        )));
+    };
+    if connector.state() != connector::State::Connected {
+        bail!(Error::Resource(format!(
+            "Connector {name} is not connected"
+        )));
+    }
+    Ok(connector)
+}
+
+/// Reads the `panel orientation` property of `connector`, which tells how a built-in panel is
+/// mounted.
+fn panel_orientation(card: &Card, connector: &connector::Info) -> Result<Orientation> {
+    let properties = card.properties(connector.handle())?;
+    let Some((info, value)) = properties.get("panel orientation") else {
+        return Ok(Orientation::UPRIGHT);
+    };
+    let property::ValueType::Enum(values) = info.value_type() else {
+        return Ok(Orientation::UPRIGHT);
+    };
+    let name = values
+        .get_value_from_raw_value(*value)
+        .map(|v| v.name().to_bytes());
+    // The side of the panel named is at the top, so the picture has to be turned towards it
+    let degrees = match name {
+        Some(b"Upside Down") => 180,
+        Some(b"Left Side Up") => 270,
+        Some(b"Right Side Up") => 90,
+        _ => 0,
+    };
+    Ok(Orientation::rotate(degrees).unwrap_or_default())
+}
@@ -0,0 +1,24 @@
+use image::{Rgba, RgbaImage};
+
+/// The colours of the classic colour bar test pattern, from left to right.
+const BARS: [Rgba<u8>; 8] = [
+    Rgba([255, 255, 255, 255]),
+    Rgba([255, 255, 0, 255]),
+    Rgba([0, 255, 255, 255]),
+    Rgba([0, 255, 0, 255]),
+    Rgba([255, 0, 255, 255]),
+    Rgba([255, 0, 0, 255]),
+    Rgba([0, 0, 255, 255]),
+    Rgba([0, 0, 0, 255]),
+];
+
+/// Draws vertical colour bars, with a one pixel white border to make clipping easy to spot.
+pub fn color_bars(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        if x == 0 || y == 0 || x + 1 == width || y + 1 == height {
+            BARS[0]
+        } else {
+            BARS[(x as usize * BARS.len()) / width as usize]
+        }
+    })
+}
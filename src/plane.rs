@@ -0,0 +1,183 @@
+use crate::{Card, PixelFormat};
+use drm::control::{crtc, plane, property, ResourceHandles};
+use std::{fmt, io};
+
+/// The kind of a plane, as given by its `type` property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaneType {
+    Overlay,
+    Primary,
+    Cursor,
+}
+
+impl fmt::Display for PlaneType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PlaneType::Overlay => "overlay",
+            PlaneType::Primary => "primary",
+            PlaneType::Cursor => "cursor",
+        })
+    }
+}
+
+/// The features of a plane that matter when choosing where to show an image.
+#[derive(Debug, Clone)]
+pub struct PlaneCapabilities {
+    pub kind: PlaneType,
+    /// The best format the plane can scan out, if drmimage knows any of them.
+    pub format: Option<PixelFormat>,
+    /// The range of the `zpos` property, if the plane has one.
+    pub zpos: Option<(i64, i64)>,
+    /// Whether the plane has an `alpha` property for blending the whole plane.
+    pub alpha: bool,
+    /// Whether the plane has a `rotation` property.
+    pub rotation: bool,
+}
+
+impl PlaneCapabilities {
+    /// Reads the properties of `plane`.
+    ///
+    /// Without universal planes the kernel only lists overlay planes and may not expose the
+    /// `type` property at all.
+    pub fn read(card: &Card, plane: &plane::Info) -> io::Result<PlaneCapabilities> {
+        let properties = card.properties(plane.handle())?;
+        let kind = match properties.get("type").map(|&(_, value)| value) {
+            Some(1) => PlaneType::Primary,
+            Some(2) => PlaneType::Cursor,
+            _ => PlaneType::Overlay,
+        };
+        let zpos = properties
+            .get("zpos")
+            .and_then(|(info, _)| match info.value_type() {
+                property::ValueType::UnsignedRange(min, max) => Some((min as i64, max as i64)),
+                property::ValueType::SignedRange(min, max) => Some((min, max)),
+                _ => None,
+            });
+        Ok(PlaneCapabilities {
+            kind,
+            format: PixelFormat::best(plane.formats()),
+            zpos,
+            alpha: properties.contains_key("alpha"),
+            rotation: properties.contains_key("rotation"),
+        })
+    }
+
+    /// Whether drmimage can show an image on `plane` on `crtc`: the plane has to be able to scan
+    /// out one of the formats drmimage writes, and has to be either an unused overlay or the
+    /// CRTC's primary plane.
+    pub fn usable_on(
+        &self,
+        resources: &ResourceHandles,
+        plane: &plane::Info,
+        crtc: crtc::Handle,
+    ) -> bool {
+        resources
+            .filter_crtcs(plane.possible_crtcs())
+            .contains(&crtc)
+            && self.format.is_some()
+            && match self.kind {
+                PlaneType::Overlay => plane.crtc().is_none(),
+                PlaneType::Primary => plane.crtc().map_or(true, |c| c == crtc),
+                PlaneType::Cursor => false,
+            }
+    }
+
+    /// How well suited the plane is for showing an image on top of the console. Higher is
+    /// better.
+    ///
+    /// Overlays always win over the primary plane, as they leave the console visible. Among
+    /// planes of the same kind, those that can be raised the furthest and that support blending
+    /// and rotation, and those that keep the transparency of the image, are preferred.
+    ///
+    /// Scaling is not ranked, as drivers don't advertise which planes can scale. The session
+    /// finds out when showing the image, and scales it in software if the plane can't.
+    pub fn score(&self) -> u32 {
+        let mut score = 0;
+        if self.kind == PlaneType::Overlay {
+            score += 1000;
+        }
+        if let Some((min, max)) = self.zpos {
+            if min < max {
+                score += 100;
+            }
+            score += max.clamp(0, 50) as u32;
+        }
+        let transparency = self.format.is_some_and(PixelFormat::has_alpha);
+        for feature in [self.alpha, self.rotation, transparency] {
+            if feature {
+                score += 10;
+            }
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane(kind: PlaneType) -> PlaneCapabilities {
+        PlaneCapabilities {
+            kind,
+            format: Some(PixelFormat::Xrgb8888),
+            zpos: None,
+            alpha: false,
+            rotation: false,
+        }
+    }
+
+    #[test]
+    fn overlays_beat_primary_planes() {
+        let primary = PlaneCapabilities {
+            format: Some(PixelFormat::Argb8888),
+            zpos: Some((0, 50)),
+            alpha: true,
+            rotation: true,
+            ..plane(PlaneType::Primary)
+        };
+        assert!(plane(PlaneType::Overlay).score() > primary.score());
+    }
+
+    #[test]
+    fn prefers_higher_and_adjustable_zpos() {
+        let fixed = |zpos| PlaneCapabilities {
+            zpos: Some((zpos, zpos)),
+            ..plane(PlaneType::Overlay)
+        };
+        assert!(fixed(3).score() > fixed(1).score());
+        assert!(fixed(1).score() > plane(PlaneType::Overlay).score());
+        let adjustable = PlaneCapabilities {
+            zpos: Some((0, 1)),
+            ..plane(PlaneType::Overlay)
+        };
+        assert!(adjustable.score() > fixed(3).score());
+        // Negative positions don't count against a plane
+        assert_eq!(fixed(-5).score(), plane(PlaneType::Overlay).score());
+    }
+
+    #[test]
+    fn prefers_more_features() {
+        let overlay = plane(PlaneType::Overlay);
+        let alpha = PlaneCapabilities {
+            alpha: true,
+            ..overlay.clone()
+        };
+        let rotation = PlaneCapabilities {
+            rotation: true,
+            ..overlay.clone()
+        };
+        let transparency = PlaneCapabilities {
+            format: Some(PixelFormat::Argb8888),
+            ..overlay.clone()
+        };
+        for better in [&alpha, &rotation, &transparency] {
+            assert!(better.score() > overlay.score());
+        }
+        let all = PlaneCapabilities {
+            alpha: true,
+            rotation: true,
+            ..transparency.clone()
+        };
+        assert!(all.score() > alpha.score());
+    }
+}
@@ -0,0 +1,372 @@
+use crate::Orientation;
+use image::{imageops, imageops::FilterType, RgbaImage};
+use std::{fmt, str::FromStr};
+
+/// How a picture is sized to the CRTC it is shown on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    /// As large as possible while still showing the whole picture, keeping its aspect ratio.
+    #[default]
+    Fit,
+    /// As small as possible while still covering the whole CRTC, keeping its aspect ratio.
+    Fill,
+    /// Exactly the size of the CRTC, ignoring the aspect ratio.
+    Stretch,
+    /// Unscaled.
+    Center,
+    /// The largest whole multiple of the picture's size that still fits, for crisp pixel art.
+    Integer,
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ScaleMode, String> {
+        match s {
+            "fit" => Ok(ScaleMode::Fit),
+            "fill" => Ok(ScaleMode::Fill),
+            "stretch" => Ok(ScaleMode::Stretch),
+            "center" => Ok(ScaleMode::Center),
+            "integer" => Ok(ScaleMode::Integer),
+            _ => Err(format!(
+                "Unknown scaling mode `{s}`, expected fit, fill, stretch, center or integer"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ScaleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScaleMode::Fit => "fit",
+            ScaleMode::Fill => "fill",
+            ScaleMode::Stretch => "stretch",
+            ScaleMode::Center => "center",
+            ScaleMode::Integer => "integer",
+        })
+    }
+}
+
+/// Which edges of the CRTC a picture is placed against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// Where a picture lies along one axis.
+enum Edge {
+    Start,
+    Middle,
+    End,
+}
+
+impl Align {
+    const NAMES: [(&'static str, Align); 9] = [
+        ("top-left", Align::TopLeft),
+        ("top", Align::Top),
+        ("top-right", Align::TopRight),
+        ("left", Align::Left),
+        ("center", Align::Center),
+        ("right", Align::Right),
+        ("bottom-left", Align::BottomLeft),
+        ("bottom", Align::Bottom),
+        ("bottom-right", Align::BottomRight),
+    ];
+
+    fn edges(self) -> (Edge, Edge) {
+        match self {
+            Align::TopLeft => (Edge::Start, Edge::Start),
+            Align::Top => (Edge::Middle, Edge::Start),
+            Align::TopRight => (Edge::End, Edge::Start),
+            Align::Left => (Edge::Start, Edge::Middle),
+            Align::Center => (Edge::Middle, Edge::Middle),
+            Align::Right => (Edge::End, Edge::Middle),
+            Align::BottomLeft => (Edge::Start, Edge::End),
+            Align::Bottom => (Edge::Middle, Edge::End),
+            Align::BottomRight => (Edge::End, Edge::End),
+        }
+    }
+}
+
+impl FromStr for Align {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Align, String> {
+        Align::NAMES
+            .iter()
+            .find(|&&(name, _)| name == s)
+            .map(|&(_, align)| align)
+            .ok_or_else(|| {
+                let names: Vec<_> = Align::NAMES.iter().map(|&(name, _)| name).collect();
+                format!(
+                    "Unknown alignment `{s}`, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for Align {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = Align::NAMES.iter().find(|&&(_, a)| a == *self).unwrap();
+        f.write_str(name)
+    }
+}
+
+/// Where a picture ends up on a CRTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub picture_size: (u32, u32),
+    pub crtc_size: (u32, u32),
+    /// The size the picture is scaled to.
+    pub scaled_size: (u32, u32),
+    /// Where the top-left corner of the scaled picture lies on the CRTC. Parts of the picture may
+    /// hang off any edge of the CRTC.
+    pub position: (i32, i32),
+}
+
+impl Geometry {
+    /// Scales a picture according to `mode` and places it on the CRTC according to `align`.
+    pub fn new(
+        mode: ScaleMode,
+        align: Align,
+        picture_size: (u32, u32),
+        crtc_size: (u32, u32),
+    ) -> Geometry {
+        let (width, height) = (picture_size.0.max(1), picture_size.1.max(1));
+        let (crtc_width, crtc_height) = crtc_size;
+        // Whether the CRTC is relatively wider than the picture, without rounding
+        let wider =
+            u64::from(crtc_width) * u64::from(height) > u64::from(crtc_height) * u64::from(width);
+        let scale_to_height = |h: u32| {
+            let w = u64::from(width) * u64::from(h) / u64::from(height);
+            (w.max(1) as u32, h)
+        };
+        let scale_to_width = |w: u32| {
+            let h = u64::from(height) * u64::from(w) / u64::from(width);
+            (w, h.max(1) as u32)
+        };
+        let scaled_size = match mode {
+            ScaleMode::Fit if wider => scale_to_height(crtc_height),
+            ScaleMode::Fit => scale_to_width(crtc_width),
+            ScaleMode::Fill if wider => scale_to_width(crtc_width),
+            ScaleMode::Fill => scale_to_height(crtc_height),
+            ScaleMode::Stretch => crtc_size,
+            ScaleMode::Center => picture_size,
+            ScaleMode::Integer => {
+                let factor = (crtc_width / width).min(crtc_height / height).max(1);
+                (width * factor, height * factor)
+            }
+        };
+        let place = |edge: Edge, crtc: u32, scaled: u32| {
+            let space = i64::from(crtc) - i64::from(scaled);
+            match edge {
+                Edge::Start => 0,
+                Edge::Middle => (space / 2) as i32,
+                Edge::End => space as i32,
+            }
+        };
+        let (horizontal, vertical) = align.edges();
+        Geometry {
+            picture_size,
+            crtc_size,
+            scaled_size,
+            position: (
+                place(horizontal, crtc_width, scaled_size.0),
+                place(vertical, crtc_height, scaled_size.1),
+            ),
+        }
+    }
+
+    /// Moves the top-left corner of the scaled picture to `position` on the CRTC.
+    ///
+    /// The position may be negative or past the edge of the CRTC, in which case only the part of
+    /// the picture that is still on screen is shown.
+    pub fn at(self, position: (i32, i32)) -> Geometry {
+        Geometry { position, ..self }
+    }
+
+    /// Shows the picture unscaled with its top-left corner at the CRTC origin.
+    pub fn unscaled(picture_size: (u32, u32), crtc_size: (u32, u32)) -> Geometry {
+        Geometry {
+            picture_size,
+            crtc_size,
+            scaled_size: picture_size,
+            position: (0, 0),
+        }
+    }
+
+    /// The same placement, seen after turning the CRTC and the picture according to
+    /// `orientation`.
+    pub fn turned(self, orientation: Orientation) -> Geometry {
+        let (x, y, width, height) = orientation.turn_rect(
+            (
+                self.position.0.into(),
+                self.position.1.into(),
+                self.scaled_size.0.into(),
+                self.scaled_size.1.into(),
+            ),
+            (self.crtc_size.0.into(), self.crtc_size.1.into()),
+        );
+        Geometry {
+            picture_size: orientation.size(self.picture_size),
+            crtc_size: orientation.size(self.crtc_size),
+            scaled_size: (width as u32, height as u32),
+            position: (x as i32, y as i32),
+        }
+    }
+
+    /// Whether the picture has to be resampled, as opposed to only being cropped.
+    pub fn is_scaled(&self) -> bool {
+        self.scaled_size != self.picture_size
+    }
+
+    /// The part of the CRTC the picture covers, as x, y, width and height.
+    ///
+    /// Returns `None` if the picture lies entirely off screen.
+    pub fn crtc_rect(&self) -> Option<(i32, i32, u32, u32)> {
+        let clip = |position: i32, scaled: u32, crtc: u32| {
+            let start = i64::from(position).max(0);
+            let end = (i64::from(position) + i64::from(scaled)).min(i64::from(crtc));
+            (start < end).then(|| (start as i32, (end - start) as u32))
+        };
+        let (x, width) = clip(self.position.0, self.scaled_size.0, self.crtc_size.0)?;
+        let (y, height) = clip(self.position.1, self.scaled_size.1, self.crtc_size.1)?;
+        Some((x, y, width, height))
+    }
+
+    /// The part of the picture that is visible in [`Self::crtc_rect`], as x, y, width and height
+    /// in 16.16 fixed point.
+    pub fn src_rect(&self) -> Option<(u32, u32, u32, u32)> {
+        let (x, y, width, height) = self.crtc_rect()?;
+        let to_picture = |crtc: i64, picture: u32, scaled: u32| {
+            ((crtc << 16) * i64::from(picture) / i64::from(scaled)) as u32
+        };
+        let (px, py) = self.position;
+        Some((
+            to_picture(i64::from(x - px), self.picture_size.0, self.scaled_size.0),
+            to_picture(i64::from(y - py), self.picture_size.1, self.scaled_size.1),
+            to_picture(i64::from(width), self.picture_size.0, self.scaled_size.0),
+            to_picture(i64::from(height), self.picture_size.1, self.scaled_size.1),
+        ))
+    }
+
+    /// Scales the visible part of `picture` in software, for planes that can't scale themselves.
+    ///
+    /// The result is exactly the size of [`Self::crtc_rect`] and can be shown unscaled.
+    pub fn resample(&self, picture: &RgbaImage) -> Option<RgbaImage> {
+        let (_, _, width, height) = self.crtc_rect()?;
+        let (x, y, src_width, src_height) = self.src_rect()?;
+        let (x, y) = (x >> 16, y >> 16);
+        let visible = imageops::crop_imm(
+            picture,
+            x,
+            y,
+            src_width.div_ceil(1 << 16),
+            src_height.div_ceil(1 << 16),
+        );
+        Some(imageops::resize(
+            &*visible,
+            width,
+            height,
+            FilterType::CatmullRom,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const CRTC: (u32, u32) = (1920, 1080);
+
+    #[test]
+    fn fit_letterboxes_the_picture() {
+        let geometry = Geometry::new(ScaleMode::Fit, Align::Center, (800, 600), CRTC);
+        assert_eq!(geometry.scaled_size, (1440, 1080));
+        assert_eq!(geometry.position, (240, 0));
+        assert_eq!(geometry.crtc_rect(), Some((240, 0, 1440, 1080)));
+        assert_eq!(geometry.src_rect(), Some((0, 0, 800 << 16, 600 << 16)));
+    }
+
+    #[test]
+    fn fill_crops_the_overhang() {
+        let geometry = Geometry::new(ScaleMode::Fill, Align::Center, (800, 600), CRTC);
+        assert_eq!(geometry.scaled_size, (1920, 1440));
+        assert_eq!(geometry.position, (0, -180));
+        assert_eq!(geometry.crtc_rect(), Some((0, 0, 1920, 1080)));
+        // 180 of the 1440 scaled rows are cut off at the top, which is 75 rows of the picture
+        assert_eq!(
+            geometry.src_rect(),
+            Some((0, 75 << 16, 800 << 16, 450 << 16))
+        );
+    }
+
+    #[test]
+    fn stretch_and_center() {
+        let stretch = Geometry::new(ScaleMode::Stretch, Align::Center, (800, 600), CRTC);
+        assert_eq!(stretch.scaled_size, CRTC);
+        assert_eq!(stretch.position, (0, 0));
+        let center = Geometry::new(ScaleMode::Center, Align::BottomRight, (800, 600), CRTC);
+        assert_eq!(center.scaled_size, (800, 600));
+        assert_eq!(center.position, (1120, 480));
+        assert!(!center.is_scaled());
+    }
+
+    #[test]
+    fn integer_uses_whole_multiples() {
+        let geometry = Geometry::new(ScaleMode::Integer, Align::TopLeft, (320, 200), CRTC);
+        assert_eq!(geometry.scaled_size, (1600, 1000));
+        assert_eq!(geometry.position, (0, 0));
+        // Pictures larger than the CRTC are never shrunk
+        let large = Geometry::new(ScaleMode::Integer, Align::TopLeft, (4000, 3000), CRTC);
+        assert_eq!(large.scaled_size, (4000, 3000));
+    }
+
+    #[test]
+    fn negative_position_clips_the_picture() {
+        let geometry = Geometry::unscaled((800, 600), CRTC).at((-100, 50));
+        assert_eq!(geometry.crtc_rect(), Some((0, 50, 700, 600)));
+        assert_eq!(
+            geometry.src_rect(),
+            Some((100 << 16, 0, 700 << 16, 600 << 16))
+        );
+    }
+
+    #[test]
+    fn off_screen_picture_has_no_rects() {
+        let geometry = Geometry::unscaled((800, 600), CRTC).at((1920, 0));
+        assert_eq!(geometry.crtc_rect(), None);
+        assert_eq!(geometry.src_rect(), None);
+        assert!(geometry.resample(&RgbaImage::new(800, 600)).is_none());
+    }
+
+    #[test]
+    fn resample_matches_the_crtc_rect() {
+        let picture = RgbaImage::from_pixel(800, 600, Rgba([10, 20, 30, 255]));
+        let geometry = Geometry::new(ScaleMode::Fill, Align::Center, (800, 600), CRTC);
+        let resampled = geometry.resample(&picture).unwrap();
+        assert_eq!(resampled.dimensions(), CRTC);
+        assert_eq!(resampled.get_pixel(960, 540), &Rgba([10, 20, 30, 255]));
+    }
+
+    #[test]
+    fn parses_modes_and_alignments() {
+        assert_eq!("integer".parse(), Ok(ScaleMode::Integer));
+        assert!("zoom".parse::<ScaleMode>().is_err());
+        for (name, align) in Align::NAMES {
+            assert_eq!(name.parse(), Ok(align));
+            assert_eq!(align.to_string(), name);
+        }
+    }
+}
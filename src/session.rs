@@ -0,0 +1,714 @@
+use crate::atomic::AtomicRequest;
+use crate::{
+    Animation, Background, Card, CrtcState, DisplayOptions, DumbFramebuffer, Error, Geometry,
+    Layout, Orientation, Output, PixelFormat, PlaneState,
+};
+use drm::control::{
+    framebuffer, AtomicCommitFlags, Device as _, Event, PageFlipFlags, ResourceHandles,
+};
+use drm::Device as _;
+use eyre::{bail, Result};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use std::{
+    borrow::Cow,
+    mem,
+    time::{Duration, Instant},
+};
+
+/// An image being shown on the planes of one or more outputs.
+///
+/// The session owns the card along with the buffers and framebuffers backing the planes. Closing
+/// or dropping it puts the planes back the way they were and frees the buffers.
+pub struct DisplaySession {
+    card: Card,
+    screens: Vec<Screen>,
+    closed: bool,
+}
+
+/// Everything drmimage set up on a single output.
+struct Screen {
+    output: Output,
+    geometry: Geometry,
+    /// How the plane turns the image. The image has already been turned in software if the
+    /// plane can't do it.
+    orientation: Orientation,
+    format: PixelFormat,
+    image: DumbFramebuffer,
+    /// How the uploaded image was made from the picture the session was created with, so that
+    /// the frames of an animation can be made the same way.
+    steps: Vec<Step>,
+    /// The buffer the next frame of an animation is drawn into while `image` is on screen.
+    spare: Option<DumbFramebuffer>,
+    /// The black framebuffer scanned out by the CRTC, if we had to modeset it ourselves.
+    background: Option<DumbFramebuffer>,
+    previous_plane: Option<PlaneState>,
+    previous_crtc: Option<CrtcState>,
+}
+
+/// A picture waiting to be shown on an output.
+struct Pending<'a> {
+    output: Output,
+    picture: Cow<'a, RgbaImage>,
+    /// How `picture` was made from the picture passed to the session.
+    steps: Vec<Step>,
+    geometry: Geometry,
+    orientation: Orientation,
+}
+
+/// Something done to a picture in software before uploading it.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Turn(Orientation),
+    Crop((u32, u32, u32, u32)),
+    Compose(Background, Geometry),
+    Resample(Geometry),
+}
+
+impl Step {
+    fn apply(&self, picture: &RgbaImage) -> Result<RgbaImage> {
+        Ok(match *self {
+            Step::Turn(orientation) => orientation.apply(picture),
+            Step::Crop((x, y, width, height)) => {
+                imageops::crop_imm(picture, x, y, width, height).to_image()
+            }
+            Step::Compose(background, geometry) => background.compose(picture, &geometry).0,
+            Step::Resample(geometry) => geometry.resample(picture).ok_or_else(|| {
+                Error::Resource("The picture lies entirely outside of the screen".to_owned())
+            })?,
+        })
+    }
+}
+
+impl DisplaySession {
+    /// Shows `picture` on the first connected output of `card`.
+    pub fn new(card: Card, picture: &RgbaImage) -> Result<DisplaySession> {
+        DisplaySession::with_options(card, picture, &DisplayOptions::default())
+    }
+
+    /// Shows `picture` on `card` as configured by `options`.
+    pub fn with_options(
+        card: Card,
+        picture: &RgbaImage,
+        options: &DisplayOptions,
+    ) -> Result<DisplaySession> {
+        // Make sure we have master
+        card.acquire_master_lock().map_err(Error::Master)?;
+        let resources = card.resource_handles()?;
+        let turned;
+        let screens = if let Some(layout) = &options.layout {
+            // The layout is in the coordinates of the turned picture, so turn it before cutting
+            if options.orientation.is_upright() {
+                DisplaySession::span(&card, &resources, picture, layout)?
+            } else {
+                turned = options.orientation.apply(picture);
+                let mut screens = DisplaySession::span(&card, &resources, &turned, layout)?;
+                for screen in &mut screens {
+                    screen.steps.insert(0, Step::Turn(options.orientation));
+                }
+                screens
+            }
+        } else if options.all_outputs {
+            Output::find_all(&card, &resources)?
+                .into_iter()
+                .map(|output| {
+                    let geometry = options.geometry(picture.dimensions(), output.view_size());
+                    Pending {
+                        output,
+                        picture: Cow::Borrowed(picture),
+                        steps: Vec::new(),
+                        geometry,
+                        orientation: options.orientation,
+                    }
+                })
+                .collect()
+        } else {
+            let mut output = Output::find(&card, &resources, options.connector.as_deref())?;
+            if let Some(plane) = options.plane {
+                output = output.with_plane(&card, &resources, plane)?;
+            }
+            let geometry = options.geometry(picture.dimensions(), output.view_size());
+            vec![Pending {
+                output,
+                picture: Cow::Borrowed(picture),
+                steps: Vec::new(),
+                geometry,
+                orientation: options.orientation,
+            }]
+        };
+        DisplaySession::show(card, &resources, screens, options)
+    }
+
+    /// Shows `picture` on the first connected output of `card`, converting it to RGBA first.
+    pub fn from_dynamic_image(card: Card, picture: &DynamicImage) -> Result<DisplaySession> {
+        DisplaySession::new(card, &picture.to_rgba8())
+    }
+
+    /// Shows a different picture on each of the named connectors, such as
+    /// `[("HDMI-A-1", &left), ("DP-1", &right)]`.
+    ///
+    /// The output selection fields of `options` are ignored.
+    pub fn per_connector(
+        card: Card,
+        pictures: &[(&str, &RgbaImage)],
+        options: &DisplayOptions,
+    ) -> Result<DisplaySession> {
+        card.acquire_master_lock().map_err(Error::Master)?;
+        let resources = card.resource_handles()?;
+        let names: Vec<&str> = pictures.iter().map(|&(name, _)| name).collect();
+        let outputs = Output::find_named(&card, &resources, &names)?;
+        let screens = outputs
+            .into_iter()
+            .zip(pictures)
+            .map(|(output, &(_, picture))| {
+                let geometry = options.geometry(picture.dimensions(), output.view_size());
+                Pending {
+                    output,
+                    picture: Cow::Borrowed(picture),
+                    steps: Vec::new(),
+                    geometry,
+                    orientation: options.orientation,
+                }
+            })
+            .collect();
+        DisplaySession::show(card, &resources, screens, options)
+    }
+
+    /// Cuts the part of `picture` each output of `layout` covers.
+    fn span<'a>(
+        card: &Card,
+        resources: &ResourceHandles,
+        picture: &'a RgbaImage,
+        layout: &Layout,
+    ) -> Result<Vec<Pending<'a>>> {
+        let names: Vec<&str> = layout
+            .placements
+            .iter()
+            .map(|placement| placement.connector.as_str())
+            .collect();
+        let outputs = Output::find_named(card, resources, &names)?;
+        let mut screens = Vec::new();
+        for (placement, output) in layout.placements.iter().zip(outputs) {
+            let (x, y) = placement.position;
+            let (width, height) = output.view_size();
+            let crop = Step::Crop((x, y, width, height));
+            let part = crop.apply(picture)?;
+            if part.width() == 0 || part.height() == 0 {
+                bail!(Error::Resource(format!(
+                    "Output {} at {x},{y} lies outside of the {}x{} image",
+                    placement.connector,
+                    picture.width(),
+                    picture.height()
+                )));
+            }
+            // The parts line up with the outputs exactly, so they must not be scaled or moved
+            let geometry = Geometry::unscaled(part.dimensions(), output.view_size());
+            screens.push(Pending {
+                output,
+                picture: Cow::Owned(part),
+                steps: vec![crop],
+                geometry,
+                orientation: Orientation::UPRIGHT,
+            });
+        }
+        Ok(screens)
+    }
+
+    /// Uploads every picture first and only then attaches them to their planes, so that the
+    /// outputs change as close together as possible.
+    ///
+    /// Each picture is turned according to its orientation and that of the panel, by the plane if
+    /// it can.
+    fn show(
+        card: Card,
+        resources: &ResourceHandles,
+        screens: Vec<Pending>,
+        options: &DisplayOptions,
+    ) -> Result<DisplaySession> {
+        let mut session = DisplaySession {
+            card,
+            screens: Vec::with_capacity(screens.len()),
+            closed: false,
+        };
+        let mut pictures = Vec::with_capacity(screens.len());
+        for Pending {
+            output,
+            mut picture,
+            mut steps,
+            mut geometry,
+            mut orientation,
+        } in screens
+        {
+            if !output.panel_orientation.is_upright() {
+                orientation = orientation.then(output.panel_orientation);
+                geometry = geometry.turned(output.panel_orientation);
+            }
+            // Nothing of what was on screen before shows through if we replace the primary plane
+            let background = options.background.or(output
+                .covers_crtc()
+                .then_some(Background::Color(Rgba([0, 0, 0, 255]))));
+            // The background is drawn around the turned picture, so the plane can't turn it
+            if !orientation.is_upright()
+                && (background.is_some() || !output.can_rotate(&session.card)?)
+            {
+                let turn = Step::Turn(orientation);
+                picture = Cow::Owned(turn.apply(&picture)?);
+                steps.push(turn);
+                orientation = Orientation::UPRIGHT;
+            }
+            if let Some(background) = background {
+                let (canvas, canvas_geometry) = background.compose(&picture, &geometry);
+                picture = Cow::Owned(canvas);
+                steps.push(Step::Compose(background, geometry));
+                geometry = canvas_geometry;
+            }
+            let mut screen = Screen::new(
+                &session.card,
+                resources,
+                output,
+                &picture,
+                geometry,
+                orientation,
+            )?;
+            screen.steps = steps;
+            session.screens.push(screen);
+            pictures.push(picture);
+        }
+        if !session.commit_atomic(resources) {
+            for screen in &session.screens {
+                screen.modeset(&session.card)?;
+            }
+            for (screen, picture) in session.screens.iter_mut().zip(&pictures) {
+                screen.attach(&session.card, picture)?;
+            }
+        }
+        session.card.release_master_lock()?;
+        Ok(session)
+    }
+
+    /// Shows every screen in a single atomic commit, after checking that the driver accepts it.
+    ///
+    /// Returns `false` without changing anything if the driver doesn't support atomic commits or
+    /// rejects the request, in which case the legacy ioctls have to be used instead.
+    fn commit_atomic(&self, resources: &ResourceHandles) -> bool {
+        if !self.card.supports_atomic() || self.screens.iter().any(|s| s.output.plane.is_none()) {
+            return false;
+        }
+        let mut request = AtomicRequest::new(&self.card);
+        let mut flags = AtomicCommitFlags::empty();
+        for screen in &self.screens {
+            if screen.add_to(&self.card, resources, &mut request).is_err() {
+                return false;
+            }
+            if screen.output.needs_modeset {
+                flags |= AtomicCommitFlags::ALLOW_MODESET;
+            }
+        }
+        request.test(flags).is_ok() && request.commit(flags).is_ok()
+    }
+
+    /// The card the image is being displayed on.
+    pub fn card(&self) -> &Card {
+        &self.card
+    }
+
+    /// The connector, CRTC and plane the image is being displayed on. When mirroring, this is
+    /// the first of the outputs.
+    pub fn output(&self) -> &Output {
+        &self.screens[0].output
+    }
+
+    /// All the outputs the image is being displayed on.
+    pub fn outputs(&self) -> impl Iterator<Item = &Output> {
+        self.screens.iter().map(|screen| &screen.output)
+    }
+
+    /// The framebuffers attached to the planes, in the same order as [`Self::outputs`].
+    pub fn framebuffers(&self) -> impl Iterator<Item = framebuffer::Handle> + '_ {
+        self.screens.iter().map(|screen| screen.image.handle)
+    }
+
+    /// Plays `animation` on every output, starting at its second frame as the session is expected
+    /// to have been created with the first one.
+    ///
+    /// Frames are decoded one at a time, and each is drawn into a spare buffer while the previous
+    /// one is on screen. The two are flipped on the next vertical blank once the previous frame's
+    /// delay is over. `wait` is called with the time left until then, and should sleep for it and
+    /// return `true`, or return `false` to stop playing. Playback also stops after the number of
+    /// plays the file asks for, leaving the last frame on screen.
+    ///
+    /// DRM master is only held while flipping, like it is only held while showing the first
+    /// frame.
+    pub fn play(
+        &mut self,
+        animation: &Animation,
+        mut wait: impl FnMut(Duration) -> bool,
+    ) -> Result<()> {
+        let mut delay = animation.first().delay;
+        let mut shown_at = Instant::now();
+        let mut plays = 0;
+        loop {
+            let mut flipped_any = false;
+            // The first frame is already on screen the first time round
+            for frame in animation.frames()?.skip(usize::from(plays == 0)) {
+                let frame = frame?;
+                for screen in &mut self.screens {
+                    screen.prepare(&self.card, &frame.picture)?;
+                }
+                if !wait(delay.saturating_sub(shown_at.elapsed())) {
+                    return Ok(());
+                }
+                // Only hold master while flipping, so that other programs can take over the
+                // display in between frames
+                self.card.acquire_master_lock().map_err(Error::Master)?;
+                let flipped = self.flip();
+                self.card.release_master_lock()?;
+                flipped?;
+                delay = frame.delay;
+                shown_at = Instant::now();
+                flipped_any = true;
+            }
+            if !flipped_any {
+                bail!("The animation has no frames left to play");
+            }
+            plays += 1;
+            if animation.plays.is_some_and(|limit| plays >= limit) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Puts the spare buffers of every screen on screen and waits for the vertical blank.
+    ///
+    /// A single atomic commit flips all outputs at once. Without atomic modesetting CRTCs
+    /// scanning out the image are page flipped, and overlay planes are attached anew, which the
+    /// kernel syncs to the vertical blank itself.
+    fn flip(&mut self) -> Result<()> {
+        for screen in &mut self.screens {
+            if let Some(spare) = &mut screen.spare {
+                mem::swap(&mut screen.image, spare);
+            }
+        }
+        let mut flips = 0;
+        if self.flip_atomic() {
+            flips = self.screens.len();
+        } else {
+            for screen in &self.screens {
+                let crtc_rect = screen.geometry.crtc_rect().unwrap();
+                if screen.output.covers_crtc() {
+                    self.card.page_flip(
+                        screen.output.crtc.handle(),
+                        screen.image.handle,
+                        PageFlipFlags::EVENT,
+                        None,
+                    )?;
+                    flips += 1;
+                } else {
+                    screen.set_plane(&self.card, crtc_rect, screen.src_rect())?;
+                }
+            }
+        }
+        // Only draw into the buffers taken off screen once the hardware has let go of them
+        while flips > 0 {
+            for event in self.card.receive_events()? {
+                if let Event::PageFlip(_) = event {
+                    flips -= 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Attaches the images of every screen in a single atomic commit that sends an event for each
+    /// CRTC once it has flipped.
+    fn flip_atomic(&self) -> bool {
+        if !self.card.supports_atomic() {
+            return false;
+        }
+        let mut request = AtomicRequest::new(&self.card);
+        for screen in &self.screens {
+            let Some(plane) = &screen.output.plane else {
+                return false;
+            };
+            if request
+                .set_object(plane.handle(), "FB_ID", screen.image.handle)
+                .is_err()
+            {
+                return false;
+            }
+        }
+        request
+            .commit(AtomicCommitFlags::PAGE_FLIP_EVENT | AtomicCommitFlags::NONBLOCK)
+            .is_ok()
+    }
+
+    /// Restores the planes and CRTCs to their previous state and frees the buffers.
+    pub fn close(mut self) -> Result<()> {
+        self.restore()
+    }
+
+    fn restore(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        // Someone else may have become master in the meantime, in which case removing our
+        // framebuffers below still takes the image off the screen.
+        let master = self.card.acquire_master_lock().is_ok();
+        let mut restored = Ok(());
+        for screen in self.screens.drain(..) {
+            restored = restored.and(screen.restore(&self.card));
+        }
+        if master {
+            self.card.release_master_lock()?;
+        }
+        restored
+    }
+}
+
+impl Drop for DisplaySession {
+    fn drop(&mut self) {
+        let _ = self.restore();
+    }
+}
+
+impl Screen {
+    /// Records the state of the output and uploads `picture`, without changing what is shown.
+    fn new(
+        card: &Card,
+        resources: &ResourceHandles,
+        output: Output,
+        picture: &RgbaImage,
+        geometry: Geometry,
+        orientation: Orientation,
+    ) -> Result<Screen> {
+        if geometry.crtc_rect().is_none() {
+            bail!(Error::Resource(format!(
+                "The picture lies entirely outside of output {}",
+                output.connector
+            )));
+        }
+        let mut previous_plane = None;
+        // Without a plane the image is scanned out by the CRTC itself, which every driver
+        // supports in Xrgb8888
+        let mut format = PixelFormat::Xrgb8888;
+        if let Some(plane) = &output.plane {
+            let Some(best) = PixelFormat::best(plane.formats()) else {
+                bail!(Error::Format(format!(
+                    "Plane {} supports none of the pixel formats drmimage can write",
+                    u32::from(plane.handle())
+                )));
+            };
+            format = best;
+            // The primary plane is put back by restoring the CRTC, which also knows which part
+            // of the framebuffer it was showing
+            if !output.primary {
+                previous_plane = Some(PlaneState::snapshot(card, plane)?);
+            }
+        }
+        let mut previous_crtc = None;
+        if output.needs_modeset || output.covers_crtc() {
+            previous_crtc = Some(CrtcState::snapshot(card, resources, &output.crtc)?);
+        }
+        let mut background = None;
+        if output.needs_modeset {
+            let (width, height) = output.mode.size();
+            background = Some(DumbFramebuffer::black(card, (width.into(), height.into()))?);
+        }
+        let image = match DumbFramebuffer::from_image(card, picture, format) {
+            Ok(image) => image,
+            Err(e) => {
+                if let Some(background) = background {
+                    background.destroy(card)?;
+                }
+                return Err(e.into());
+            }
+        };
+        Ok(Screen {
+            output,
+            geometry,
+            orientation,
+            format,
+            image,
+            steps: Vec::new(),
+            spare: None,
+            background,
+            previous_plane,
+            previous_crtc,
+        })
+    }
+
+    /// Lights up the CRTC with a black background if it was not active yet.
+    fn modeset(&self, card: &Card) -> Result<()> {
+        if let Some(background) = &self.background {
+            card.set_crtc(
+                self.output.crtc.handle(),
+                Some(background.handle),
+                (0, 0),
+                &[self.output.connector.handle()],
+                Some(self.output.mode),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Adds what [`Self::modeset`] and [`Self::attach`] would do to an atomic `request`.
+    fn add_to(
+        &self,
+        card: &Card,
+        resources: &ResourceHandles,
+        request: &mut AtomicRequest,
+    ) -> Result<()> {
+        let Some(plane) = &self.output.plane else {
+            bail!("Output {} has no plane", self.output.connector);
+        };
+        let crtc = self.output.crtc.handle();
+        if let Some(background) = &self.background {
+            request.set_object(self.output.connector.handle(), "CRTC_ID", crtc)?;
+            request.set_mode(crtc, &self.output.mode)?;
+            request.set(crtc, "ACTIVE", 1)?;
+            if !self.output.primary {
+                let Some(primary) = card.get_primary_plane(resources, crtc)? else {
+                    bail!(
+                        "Failed to find a primary plane for output {}",
+                        self.output.connector
+                    );
+                };
+                let (width, height) = background.size();
+                request.set_plane(
+                    primary,
+                    crtc,
+                    background.handle,
+                    (0, 0, width, height),
+                    (0, 0, width << 16, height << 16),
+                )?;
+            }
+        }
+        request.set_plane(
+            plane.handle(),
+            crtc,
+            self.image.handle,
+            self.geometry.crtc_rect().unwrap(),
+            self.src_rect(),
+        )?;
+        if request.has(plane.handle(), "rotation")? {
+            request.set(plane.handle(), "rotation", self.orientation.drm_rotation())?;
+        }
+        Ok(())
+    }
+
+    /// Shows the image on the plane, scaling it in software if the plane can't do it.
+    fn attach(&mut self, card: &Card, picture: &RgbaImage) -> Result<()> {
+        if self.output.plane.is_none() {
+            // The image has been padded to the size of the CRTC
+            card.set_crtc(
+                self.output.crtc.handle(),
+                Some(self.image.handle),
+                (0, 0),
+                &[self.output.connector.handle()],
+                Some(self.output.mode),
+            )?;
+            return Ok(());
+        }
+        let crtc_rect = self.geometry.crtc_rect().unwrap();
+        if !self.orientation.is_upright() {
+            let plane = self.output.plane.as_ref().unwrap().handle();
+            let rotation = self.orientation.drm_rotation();
+            let attached = card
+                .set_property_by_name(plane, "rotation", rotation)
+                .and_then(|()| self.set_plane(card, crtc_rect, self.src_rect()));
+            if attached.is_ok() {
+                return attached;
+            }
+            // Turn the picture in software instead
+            let upright = Orientation::UPRIGHT.drm_rotation();
+            let _ = card.set_property_by_name(plane, "rotation", upright);
+            let turn = Step::Turn(self.orientation);
+            let turned = turn.apply(picture)?;
+            self.steps.push(turn);
+            self.orientation = Orientation::UPRIGHT;
+            self.replace_image(card, &turned)?;
+            return self.attach(card, &turned);
+        }
+        let attached = self.set_plane(card, crtc_rect, self.src_rect());
+        if attached.is_ok() || !self.geometry.is_scaled() {
+            return attached;
+        }
+        let resample = Step::Resample(self.geometry);
+        let resampled = resample.apply(picture)?;
+        self.replace_image(card, &resampled)?;
+        self.steps.push(resample);
+        // The resampled picture covers exactly the visible part of the CRTC
+        let (x, y, _, _) = crtc_rect;
+        self.geometry =
+            Geometry::unscaled(resampled.dimensions(), self.geometry.crtc_size).at((x, y));
+        self.set_plane(card, crtc_rect, self.src_rect())
+    }
+
+    /// Draws the frame `picture` into the spare buffer, the same way the image was made from the
+    /// picture the session was created with.
+    fn prepare(&mut self, card: &Card, picture: &RgbaImage) -> Result<()> {
+        let frame = self
+            .steps
+            .iter()
+            .try_fold(Cow::Borrowed(picture), |frame, step| {
+                step.apply(&frame).map(Cow::Owned)
+            })?;
+        match &mut self.spare {
+            Some(spare) => spare.write(card, &frame, self.format)?,
+            None => self.spare = Some(DumbFramebuffer::from_image(card, &frame, self.format)?),
+        }
+        Ok(())
+    }
+
+    /// The part of the framebuffer that is shown, in 16.16 fixed point.
+    fn src_rect(&self) -> (u32, u32, u32, u32) {
+        let src_rect = self.geometry.src_rect().unwrap();
+        self.orientation
+            .source_rect(src_rect, self.geometry.picture_size)
+    }
+
+    /// Uploads `picture` and frees the previous image.
+    fn replace_image(&mut self, card: &Card, picture: &RgbaImage) -> Result<()> {
+        let image = DumbFramebuffer::from_image(card, picture, self.format)?;
+        mem::replace(&mut self.image, image).destroy(card)?;
+        Ok(())
+    }
+
+    fn set_plane(
+        &self,
+        card: &Card,
+        crtc_rect: (i32, i32, u32, u32),
+        src_rect: (u32, u32, u32, u32),
+    ) -> Result<()> {
+        let Some(plane) = &self.output.plane else {
+            bail!("Output {} has no plane", self.output.connector);
+        };
+        card.set_plane(
+            plane.handle(),
+            self.output.crtc.handle(),
+            Some(self.image.handle),
+            0,
+            crtc_rect,
+            src_rect,
+        )?;
+        Ok(())
+    }
+
+    fn restore(self, card: &Card) -> Result<()> {
+        let mut restored = match &self.previous_plane {
+            Some(plane) => plane.restore(card, self.output.crtc.handle()),
+            None => Ok(()),
+        };
+        if let Some(crtc) = &self.previous_crtc {
+            restored = restored.and(crtc.restore(card));
+        }
+        self.image.destroy(card)?;
+        if let Some(spare) = self.spare {
+            spare.destroy(card)?;
+        }
+        if let Some(background) = self.background {
+            background.destroy(card)?;
+        }
+        restored
+    }
+}
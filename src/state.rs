@@ -0,0 +1,125 @@
+use crate::Card;
+use drm::control::{connector, crtc, framebuffer, plane, Device as _, Mode, ResourceHandles};
+use eyre::Result;
+
+/// The configuration of a plane before drmimage changed it.
+#[derive(Debug, Clone, Copy)]
+pub struct PlaneState {
+    pub handle: plane::Handle,
+    pub crtc: Option<crtc::Handle>,
+    pub fb: Option<framebuffer::Handle>,
+    /// Destination rectangle on the CRTC, in pixels.
+    pub crtc_rect: (i32, i32, u32, u32),
+    /// Source rectangle in the framebuffer, in 16.16 fixed point.
+    pub src_rect: (u32, u32, u32, u32),
+    /// The value of the `rotation` property, if the plane has one.
+    pub rotation: Option<u64>,
+}
+
+impl PlaneState {
+    /// Records the current state of `plane`.
+    ///
+    /// The rectangles are only exposed as properties to atomic clients, so when they are missing
+    /// the whole framebuffer is assumed to be shown unscaled at the CRTC origin.
+    pub fn snapshot(card: &Card, plane: &plane::Info) -> Result<PlaneState> {
+        let properties = card.property_values(plane.handle())?;
+        let fb_size = match plane.framebuffer() {
+            Some(fb) => card.get_framebuffer(fb)?.size(),
+            None => (0, 0),
+        };
+        let get = |name: &str, default: u64| properties.get(name).copied().unwrap_or(default);
+        Ok(PlaneState {
+            handle: plane.handle(),
+            crtc: plane.crtc(),
+            fb: plane.framebuffer(),
+            crtc_rect: (
+                get("CRTC_X", 0) as i64 as i32,
+                get("CRTC_Y", 0) as i64 as i32,
+                get("CRTC_W", fb_size.0.into()) as u32,
+                get("CRTC_H", fb_size.1.into()) as u32,
+            ),
+            src_rect: (
+                get("SRC_X", 0) as u32,
+                get("SRC_Y", 0) as u32,
+                get("SRC_W", u64::from(fb_size.0) << 16) as u32,
+                get("SRC_H", u64::from(fb_size.1) << 16) as u32,
+            ),
+            rotation: properties.get("rotation").copied(),
+        })
+    }
+
+    /// Puts the plane back into the recorded state.
+    ///
+    /// `crtc` is the CRTC the plane was moved to, used to disable it again if it was unused before.
+    pub fn restore(&self, card: &Card, crtc: crtc::Handle) -> Result<()> {
+        if let Some(rotation) = self.rotation {
+            card.set_property_by_name(self.handle, "rotation", rotation)?;
+        }
+        match (self.crtc, self.fb) {
+            (Some(crtc), Some(fb)) => {
+                card.set_plane(
+                    self.handle,
+                    crtc,
+                    Some(fb),
+                    0,
+                    self.crtc_rect,
+                    self.src_rect,
+                )?;
+            }
+            _ => card.set_plane(self.handle, crtc, None, 0, (0, 0, 0, 0), (0, 0, 0, 0))?,
+        }
+        Ok(())
+    }
+}
+
+/// The configuration of a CRTC before drmimage changed it.
+#[derive(Debug, Clone)]
+pub struct CrtcState {
+    pub handle: crtc::Handle,
+    pub fb: Option<framebuffer::Handle>,
+    pub position: (u32, u32),
+    pub mode: Option<Mode>,
+    /// The connectors the CRTC was driving.
+    pub connectors: Vec<connector::Handle>,
+}
+
+impl CrtcState {
+    /// Records the current state of `crtc`.
+    pub fn snapshot(
+        card: &Card,
+        resources: &ResourceHandles,
+        crtc: &crtc::Info,
+    ) -> Result<CrtcState> {
+        let mut connectors = Vec::new();
+        for &handle in resources.connectors() {
+            let Some(encoder) = card.get_connector(handle, false)?.current_encoder() else {
+                continue;
+            };
+            if card.get_encoder(encoder)?.crtc() == Some(crtc.handle()) {
+                connectors.push(handle);
+            }
+        }
+        Ok(CrtcState {
+            handle: crtc.handle(),
+            fb: crtc.framebuffer(),
+            position: crtc.position(),
+            mode: crtc.mode(),
+            connectors,
+        })
+    }
+
+    /// Puts the CRTC back into the recorded state, switching it off if it was inactive.
+    pub fn restore(&self, card: &Card) -> Result<()> {
+        match self.mode {
+            Some(mode) => card.set_crtc(
+                self.handle,
+                self.fb,
+                self.position,
+                &self.connectors,
+                Some(mode),
+            )?,
+            None => card.set_crtc(self.handle, None, (0, 0), &[], None)?,
+        }
+        Ok(())
+    }
+}